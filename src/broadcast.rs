@@ -0,0 +1,60 @@
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use nix::sys::stat::{self, Mode};
+
+/// Streams the session's output live to read-only viewers connected to a Unix socket, e.g.
+/// with `nc -U`. Viewers only see what is printed from the time they connect, and anything
+/// they send is ignored.
+pub struct Broadcast {
+    listener: UnixListener,
+    path: PathBuf,
+    viewers: Vec<UnixStream>,
+}
+
+impl Broadcast {
+    /// Creates the socket, readable and writable only by the owner. It is created that way
+    /// rather than restricted afterwards, so that nobody else can connect in between.
+    pub fn bind(path: &Path) -> io::Result<Broadcast> {
+        let umask = stat::umask(Mode::S_IRWXG | Mode::S_IRWXO);
+        let bound = UnixListener::bind(path);
+        stat::umask(umask);
+        let listener = bound?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+        Ok(Broadcast {
+            listener,
+            path: path.to_path_buf(),
+            viewers: Vec::new(),
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+
+    /// Accepts the viewers waiting to connect.
+    pub fn accept(&mut self) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.viewers.push(stream);
+            }
+        }
+    }
+
+    /// Sends output to every viewer. A viewer that can't keep up is disconnected rather than
+    /// holding up the session, as is one that has gone away.
+    pub fn send(&mut self, data: &[u8]) {
+        self.viewers.retain(|mut viewer| viewer.write_all(data).is_ok());
+    }
+}
+
+impl Drop for Broadcast {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
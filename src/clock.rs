@@ -0,0 +1,90 @@
+use std::ffi::CStr;
+use std::time::Duration;
+use std::{mem, ptr};
+
+use nix::libc;
+
+/// Formats the current time as RFC 3339, e.g. `2019-05-01T12:34:56+09:00` in local time or
+/// `2019-05-01T03:34:56Z` with `utc`.
+pub fn now(utc: bool) -> String {
+    let tm = broken_down(utc);
+
+    let zone = if utc {
+        "Z".to_owned()
+    } else {
+        let offset = tm.tm_gmtoff / 60;
+        format!(
+            "{}{:02}:{:02}",
+            if offset < 0 { '-' } else { '+' },
+            offset.abs() / 60,
+            offset.abs() % 60
+        )
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        zone
+    )
+}
+
+/// Formats the current time with a strftime(3) format.
+pub fn format(format: &CStr, utc: bool) -> String {
+    let tm = broken_down(utc);
+    let mut buf = [0u8; 256];
+    let len = unsafe { strftime(buf.as_mut_ptr() as *mut _, buf.len(), format.as_ptr(), &tm) };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+extern "C" {
+    // Not bound by the libc crate version in use.
+    fn strftime(
+        s: *mut libc::c_char,
+        max: libc::size_t,
+        format: *const libc::c_char,
+        tm: *const libc::tm,
+    ) -> libc::size_t;
+}
+
+fn broken_down(utc: bool) -> libc::tm {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe {
+        let t = libc::time(ptr::null_mut());
+        if utc {
+            libc::gmtime_r(&t, &mut tm);
+        } else {
+            libc::localtime_r(&t, &mut tm);
+        }
+    }
+    tm
+}
+
+/// Total time the system has been suspended since boot: the difference between
+/// CLOCK_BOOTTIME, which counts suspend, and CLOCK_MONOTONIC, which doesn't. Always zero where
+/// there is no CLOCK_BOOTTIME.
+pub fn suspended() -> Duration {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let boot = gettime(libc::CLOCK_BOOTTIME);
+        let monotonic = gettime(libc::CLOCK_MONOTONIC);
+        boot.checked_sub(monotonic).unwrap_or_default()
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    Duration::from_secs(0)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn gettime(clock: libc::clockid_t) -> Duration {
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(clock, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Formats a duration as seconds with microseconds, e.g. `1.500000`.
+pub fn seconds(d: Duration) -> String {
+    format!("{}.{:06}", d.as_secs(), d.subsec_micros())
+}
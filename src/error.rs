@@ -0,0 +1,79 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a session could not be recorded.
+#[derive(Debug)]
+pub enum Error {
+    /// stdin is not a terminal.
+    NotATty,
+    /// The terminal on stdin could not be queried or configured.
+    Terminal(nix::Error),
+    /// No pseudo-terminal could be allocated.
+    OpenPty(nix::Error),
+    /// The child process could not be forked.
+    Fork(nix::Error),
+    /// The shell could not be executed.
+    Exec(String, nix::Error),
+    /// An output file could not be opened.
+    Open(PathBuf, io::Error),
+    /// Reading or writing the session failed.
+    Io(io::Error),
+    /// A system call needed to run the session failed.
+    Sys(nix::Error),
+    /// The session was configured with options that can not be used together.
+    Invalid(&'static str),
+    /// The recorder runs inside a session that is already being recorded to this file.
+    Nested(String),
+}
+
+impl Error {
+    /// Turns an error from tcgetattr/tcsetattr on stdin into `NotATty` when it is not a tty.
+    pub(crate) fn terminal(e: nix::Error) -> Error {
+        match e {
+            nix::Error::Sys(nix::errno::Errno::ENOTTY) => Error::NotATty,
+            e => Error::Terminal(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotATty => write!(f, "stdin is not a terminal"),
+            Error::Terminal(e) => write!(f, "can not set up the terminal: {}", e),
+            Error::OpenPty(e) => write!(f, "can not open a pseudo-terminal: {}", e),
+            Error::Fork(e) => write!(f, "can not fork: {}", e),
+            Error::Exec(shell, e) => write!(f, "can not execute {}: {}", shell, e),
+            Error::Open(path, e) => write!(f, "can not open {}: {}", path.display(), e),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Sys(e) => write!(f, "{}", e),
+            Error::Invalid(msg) => write!(f, "{}", msg),
+            Error::Nested(path) => write!(f, "already recording to {} (--force records anyway)", path),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Terminal(e) | Error::OpenPty(e) | Error::Fork(e) | Error::Exec(_, e) | Error::Sys(e) => Some(e),
+            Error::Open(_, e) | Error::Io(e) => Some(e),
+            Error::NotATty | Error::Invalid(_) | Error::Nested(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<nix::Error> for Error {
+    fn from(e: nix::Error) -> Error {
+        Error::Sys(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
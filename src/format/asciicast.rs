@@ -0,0 +1,143 @@
+use std::env;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Encoder, SessionInfo};
+use crate::usage::Usage;
+
+/// asciicast v2: a JSON header line followed by one `[time, "o", data]` line per output chunk.
+pub struct Asciicast {
+    start: Duration,
+    pending: Vec<u8>,
+}
+
+impl Asciicast {
+    pub fn new() -> Asciicast {
+        Asciicast {
+            start: Duration::from_secs(0),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Decodes output as UTF-8, keeping a sequence split across chunks for the next call.
+    fn decode(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+
+        let mut text = String::new();
+        let mut rest = &self.pending[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    text.push_str(s);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push('\u{fffd}');
+                            rest = &invalid[len..];
+                        }
+                        None => {
+                            rest = invalid;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending = rest.to_vec();
+        text
+    }
+}
+
+impl Encoder for Asciicast {
+    fn header(&mut self, info: &SessionInfo, at: Duration) -> Vec<u8> {
+        self.start = at;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_secs())
+            .unwrap_or(0);
+
+        let mut header = format!(
+            "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}",
+            info.columns, info.lines, timestamp
+        );
+        if let Some(command) = info.command {
+            header.push_str(", \"command\": ");
+            push_json_string(&mut header, command);
+        }
+
+        header.push_str(", \"env\": {");
+        let mut first = true;
+        for name in &["SHELL", "TERM"] {
+            if let Ok(value) = env::var(name) {
+                if !first {
+                    header.push_str(", ");
+                }
+                first = false;
+                push_json_string(&mut header, name);
+                header.push_str(": ");
+                push_json_string(&mut header, &value);
+            }
+        }
+        header.push_str("}}\n");
+        header.into_bytes()
+    }
+
+    fn output(&mut self, data: &[u8], at: Duration) -> Vec<u8> {
+        let text = self.decode(data);
+        if text.is_empty() {
+            return Vec::new();
+        }
+
+        let time = at.checked_sub(self.start).unwrap_or_default();
+        let mut event = format!("[{}.{:06}, \"o\", ", time.as_secs(), time.subsec_micros());
+        push_json_string(&mut event, &text);
+        event.push_str("]\n");
+        event.into_bytes()
+    }
+
+    /// Information entries are marker events labelled with the lowercase name and the value,
+    /// e.g. `wallclock 1556678096.000000`.
+    fn info(&mut self, at: Duration, name: &str, value: &str) -> Vec<u8> {
+        let time = at.checked_sub(self.start).unwrap_or_default();
+        let mut event = format!("[{}.{:06}, \"m\", ", time.as_secs(), time.subsec_micros());
+        push_json_string(&mut event, &format!("{} {}", name.to_ascii_lowercase(), value));
+        event.push_str("]\n");
+        event.into_bytes()
+    }
+
+    fn resize(&mut self, at: Duration, columns: u16, lines: u16) -> Vec<u8> {
+        let time = at.checked_sub(self.start).unwrap_or_default();
+        format!("[{}.{:06}, \"r\", \"{}x{}\"]\n", time.as_secs(), time.subsec_micros(), columns, lines).into_bytes()
+    }
+
+    fn trailer(&mut self, _: i32, _: &Usage) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
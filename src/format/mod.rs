@@ -0,0 +1,80 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::usage::Usage;
+
+mod asciicast;
+mod plain;
+mod script;
+mod ttyrec;
+
+/// On-disk format of the recording.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Raw terminal output, like util-linux script.
+    Script,
+    /// asciinema's asciicast v2 JSON lines.
+    Asciicast,
+    /// ttyrec binary frames, as played by ttyplay.
+    Ttyrec,
+    /// Plain text with the escape sequences removed, for reading and grepping.
+    Plain,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "script" => Ok(Format::Script),
+            "asciicast" => Ok(Format::Asciicast),
+            "ttyrec" => Ok(Format::Ttyrec),
+            "plain" => Ok(Format::Plain),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+impl Format {
+    /// Creates an encoder for this format. `quiet` drops the optional start and done lines,
+    /// `utc` writes their dates in UTC instead of local time.
+    pub fn encoder(self, quiet: bool, utc: bool) -> Box<dyn Encoder> {
+        match self {
+            Format::Script => Box::new(script::Script::new(quiet, utc)),
+            Format::Asciicast => Box::new(asciicast::Asciicast::new()),
+            Format::Ttyrec => Box::new(ttyrec::Ttyrec::new()),
+            Format::Plain => Box::new(plain::Plain::new(quiet, utc)),
+        }
+    }
+}
+
+/// What the header of a recording describes.
+pub struct SessionInfo<'a> {
+    pub command: Option<&'a str>,
+    pub columns: u16,
+    pub lines: u16,
+}
+
+/// Turns session events into the bytes of a recording. Times are measured from the start of
+/// the session.
+pub trait Encoder {
+    /// Starts a new recording file.
+    fn header(&mut self, info: &SessionInfo, at: Duration) -> Vec<u8>;
+
+    /// Encodes a chunk of terminal output.
+    fn output(&mut self, data: &[u8], at: Duration) -> Vec<u8>;
+
+    /// Encodes an information entry about the session, such as a wall clock anchor. Formats
+    /// with no room for it leave it out.
+    fn info(&mut self, _at: Duration, _name: &str, _value: &str) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Encodes a change of the terminal size. Formats with no room for it leave it out.
+    fn resize(&mut self, _at: Duration, _columns: u16, _lines: u16) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Ends the recording after the child exited with `status`, having used `usage`.
+    fn trailer(&mut self, status: i32, usage: &Usage) -> Vec<u8>;
+}
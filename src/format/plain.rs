@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use super::script::Script;
+use super::{Encoder, SessionInfo};
+use crate::plain::PlainText;
+use crate::usage::Usage;
+
+/// A typescript with the escape sequences taken out: each line as it ended up on the screen,
+/// written once it is finished.
+pub struct Plain {
+    script: Script,
+    text: PlainText,
+}
+
+impl Plain {
+    pub fn new(quiet: bool, utc: bool) -> Plain {
+        Plain {
+            script: Script::new(quiet, utc),
+            text: PlainText::new(),
+        }
+    }
+}
+
+impl Encoder for Plain {
+    fn header(&mut self, info: &SessionInfo, at: Duration) -> Vec<u8> {
+        self.script.header(info, at)
+    }
+
+    fn output(&mut self, data: &[u8], _: Duration) -> Vec<u8> {
+        let mut lines = String::new();
+        self.text.feed(data, &mut lines);
+        lines.into_bytes()
+    }
+
+    fn trailer(&mut self, status: i32, usage: &Usage) -> Vec<u8> {
+        let mut out = self.text.current_line().into_bytes();
+        out.extend_from_slice(&self.script.trailer(status, usage));
+        out
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use nix::libc::{self, STDIN_FILENO};
+
+use super::{Encoder, SessionInfo};
+use crate::clock;
+use crate::usage::Usage;
+
+/// The util-linux typescript format: output as is, between a start and a done line.
+pub struct Script {
+    quiet: bool,
+    utc: bool,
+}
+
+impl Script {
+    pub fn new(quiet: bool, utc: bool) -> Script {
+        Script { quiet, utc }
+    }
+}
+
+impl Encoder for Script {
+    fn header(&mut self, info: &SessionInfo, _: Duration) -> Vec<u8> {
+        if self.quiet {
+            return Vec::new();
+        }
+
+        let mut fields = Vec::new();
+        if let Some(command) = info.command {
+            fields.push(format!("COMMAND=\"{}\"", command));
+        }
+        if let Ok(term) = std::env::var("TERM") {
+            fields.push(format!("TERM=\"{}\"", term));
+        }
+        let tty = unsafe { libc::ttyname(STDIN_FILENO) };
+        if !tty.is_null() {
+            let tty = unsafe { std::ffi::CStr::from_ptr(tty) };
+            fields.push(format!("TTY=\"{}\"", tty.to_string_lossy()));
+        }
+        fields.push(format!("COLUMNS=\"{}\"", info.columns));
+        fields.push(format!("LINES=\"{}\"", info.lines));
+
+        format!("Script started on {} [{}]\n", clock::now(self.utc), fields.join(" ")).into_bytes()
+    }
+
+    fn output(&mut self, data: &[u8], _: Duration) -> Vec<u8> {
+        data.to_vec()
+    }
+
+    fn trailer(&mut self, status: i32, usage: &Usage) -> Vec<u8> {
+        if self.quiet {
+            return Vec::new();
+        }
+
+        format!(
+            "\nScript done on {} [COMMAND_EXIT_CODE=\"{}\" {}]\n",
+            clock::now(self.utc),
+            status,
+            usage.fields()
+        )
+        .into_bytes()
+    }
+}
@@ -0,0 +1,42 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Encoder, SessionInfo};
+use crate::usage::Usage;
+
+/// ttyrec: each output chunk framed by a little-endian header of seconds, microseconds and
+/// length, with wall clock timestamps as ttyplay expects.
+pub struct Ttyrec {
+    epoch: Duration,
+}
+
+impl Ttyrec {
+    pub fn new() -> Ttyrec {
+        Ttyrec {
+            epoch: Duration::from_secs(0),
+        }
+    }
+}
+
+impl Encoder for Ttyrec {
+    fn header(&mut self, _: &SessionInfo, at: Duration) -> Vec<u8> {
+        // Wall clock time of the session start, so that chunk times are `epoch + at`.
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.epoch = now.checked_sub(at).unwrap_or_default();
+        Vec::new()
+    }
+
+    fn output(&mut self, data: &[u8], at: Duration) -> Vec<u8> {
+        let time = self.epoch + at;
+
+        let mut frame = Vec::with_capacity(12 + data.len());
+        frame.extend_from_slice(&(time.as_secs() as u32).to_le_bytes());
+        frame.extend_from_slice(&time.subsec_micros().to_le_bytes());
+        frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn trailer(&mut self, _: i32, _: &Usage) -> Vec<u8> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+
+use nix::libc;
+
+/// Watches directory trees with inotify and reports the files created, written, deleted and
+/// moved in them, so that changes on disk can be lined up with the session.
+///
+/// Files count as modified when they are closed after writing, which keeps a file written
+/// bit by bit, like the recording itself, down to one event.
+pub struct FsWatch {
+    fd: RawFd,
+    dirs: HashMap<i32, PathBuf>,
+}
+
+impl FsWatch {
+    pub fn new() -> io::Result<FsWatch> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(FsWatch { fd, dirs: HashMap::new() })
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        Err(io::Error::new(io::ErrorKind::Other, "watching files is only supported on Linux"))
+    }
+
+    /// Watches `root` and every directory below it. Directories created later are picked up
+    /// as they appear.
+    pub fn add(&mut self, root: &Path) -> io::Result<()> {
+        self.watch(root)?;
+        for entry in fs::read_dir(root)? {
+            let entry = entry?;
+            // Symbolic links are not followed, which also keeps loops out.
+            if entry.file_type()?.is_dir() {
+                self.add(&entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Reads the pending events as `<kind> <path>` strings, with kind one of `created`,
+    /// `modified`, `deleted`, `moved-from`, `moved-to` or `overflow` when events were lost.
+    pub fn read(&mut self) -> Vec<String> {
+        let mut changes = Vec::new();
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                let n = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+                if n <= 0 {
+                    break;
+                }
+                let mut events = &buf[..n as usize];
+                while events.len() >= std::mem::size_of::<libc::inotify_event>() {
+                    let event = unsafe { std::ptr::read_unaligned(events.as_ptr() as *const libc::inotify_event) };
+                    let header = std::mem::size_of::<libc::inotify_event>();
+                    let name = &events[header..header + event.len as usize];
+                    let name = name.split(|&b| b == 0).next().unwrap_or_default();
+                    events = &events[header + event.len as usize..];
+                    self.handle(event.wd, event.mask, name, &mut changes);
+                }
+            }
+        }
+        changes
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn handle(&mut self, wd: i32, mask: u32, name: &[u8], changes: &mut Vec<String>) {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        if mask & libc::IN_Q_OVERFLOW != 0 {
+            changes.push("overflow".to_owned());
+            return;
+        }
+        if mask & libc::IN_IGNORED != 0 {
+            self.dirs.remove(&wd);
+            return;
+        }
+        let path = match self.dirs.get(&wd) {
+            Some(dir) => dir.join(OsStr::from_bytes(name)),
+            None => return,
+        };
+
+        let kind = if mask & libc::IN_CREATE != 0 {
+            if mask & libc::IN_ISDIR != 0 {
+                let _ = self.add(&path);
+            }
+            "created"
+        } else if mask & libc::IN_CLOSE_WRITE != 0 {
+            "modified"
+        } else if mask & libc::IN_DELETE != 0 {
+            "deleted"
+        } else if mask & libc::IN_MOVED_FROM != 0 {
+            "moved-from"
+        } else if mask & libc::IN_MOVED_TO != 0 {
+            if mask & libc::IN_ISDIR != 0 {
+                let _ = self.add(&path);
+            }
+            "moved-to"
+        } else {
+            return;
+        };
+        changes.push(format!("{} {}", kind, path.display()));
+    }
+
+    fn watch(&mut self, dir: &Path) -> io::Result<()> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::ffi::CString;
+            use std::os::unix::ffi::OsStrExt;
+
+            let path = CString::new(dir.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let mask = libc::IN_CREATE
+                | libc::IN_CLOSE_WRITE
+                | libc::IN_DELETE
+                | libc::IN_MOVED_FROM
+                | libc::IN_MOVED_TO
+                | libc::IN_ONLYDIR;
+            let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), mask) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.dirs.insert(wd, dir.to_path_buf());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FsWatch {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
@@ -0,0 +1,361 @@
+use std::io::{self, Write};
+
+/// Input collected before it is compressed into a gzip member of its own.
+const MEMBER_SIZE: usize = 256 * 1024;
+
+/// Compresses what is written to it into gzip, one complete member for every `MEMBER_SIZE`
+/// bytes and on every flush. Concatenated members are a valid gzip file, so the file stays
+/// readable up to the last flush even if the recorder is killed.
+///
+/// The deflate encoding is deliberately simple: LZ77 with hash chains and the fixed Huffman
+/// codes, which is enough for terminal output and needs no tables in the stream.
+pub struct GzipWriter<W: Write> {
+    inner: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> GzipWriter<W> {
+    pub fn new(inner: W) -> GzipWriter<W> {
+        GzipWriter {
+            inner,
+            pending: Vec::with_capacity(MEMBER_SIZE),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writes out the pending input as a gzip member. The input is dropped even if that fails,
+    /// so that a full disk doesn't make it pile up.
+    fn finish_member(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let member = compress(&self.pending);
+        self.pending.clear();
+        self.inner.write_all(&member)
+    }
+}
+
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if self.pending.len() >= MEMBER_SIZE {
+            self.finish_member()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.finish_member()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for GzipWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish_member();
+    }
+}
+
+/// Compresses `data` into a complete gzip member.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter {
+        // No name or mtime, OS "Unix".
+        out: vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3],
+        acc: 0,
+        count: 0,
+    };
+    // One final block with the fixed codes.
+    bits.put(1, 1);
+    bits.put(1, 2);
+    deflate(data, &mut bits);
+    put_literal(&mut bits, 256);
+    bits.align();
+
+    let mut out = bits.out;
+
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+fn deflate(data: &[u8], bits: &mut BitWriter) {
+    let hash = |i: usize| {
+        let v = u32::from(data[i]) << 16 | u32::from(data[i + 1]) << 8 | u32::from(data[i + 2]);
+        (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    };
+    // Most recent position with each hash, and the one before it with the same hash.
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(i);
+            prev[i] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max = (data.len() - i).min(MAX_MATCH);
+            let mut candidate = head[hash(i)];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain < MAX_CHAIN {
+                let len = data[candidate..].iter().zip(&data[i..i + max]).take_while(|(a, b)| a == b).count();
+                if len > best.0 {
+                    best = (len, i - candidate);
+                    if len == max {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            put_match(bits, best.0, best.1);
+            for j in i..i + best.0 {
+                insert(j, &mut head, &mut prev);
+            }
+            i += best.0;
+        } else {
+            put_literal(bits, u16::from(data[i]));
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+fn put_match(bits: &mut BitWriter, len: usize, distance: usize) {
+    let code = LENGTH_BASE.iter().rposition(|&base| usize::from(base) <= len).unwrap_or(0);
+    put_literal(bits, 257 + code as u16);
+    bits.put((len - usize::from(LENGTH_BASE[code])) as u32, LENGTH_EXTRA[code]);
+
+    let code = DISTANCE_BASE.iter().rposition(|&base| usize::from(base) <= distance).unwrap_or(0);
+    bits.put_code(code as u32, 5);
+    bits.put((distance - usize::from(DISTANCE_BASE[code])) as u32, DISTANCE_EXTRA[code]);
+}
+
+/// Writes a literal/length symbol with its fixed Huffman code (RFC 1951, 3.2.6).
+fn put_literal(bits: &mut BitWriter, symbol: u16) {
+    let symbol = u32::from(symbol);
+    match symbol {
+        0..=143 => bits.put_code(0x30 + symbol, 8),
+        144..=255 => bits.put_code(0x190 + symbol - 144, 9),
+        256..=279 => bits.put_code(symbol - 256, 7),
+        _ => bits.put_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    /// Writes `len` bits of `value`, least significant first, as deflate stores numbers.
+    fn put(&mut self, value: u32, len: u8) {
+        for bit in 0..len {
+            self.acc |= ((value >> bit) & 1) << self.count;
+            self.count += 1;
+            if self.count == 8 {
+                self.out.push(self.acc as u8);
+                self.acc = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    /// Writes a Huffman code, which deflate stores most significant bit first.
+    fn put_code(&mut self, code: u32, len: u8) {
+        let reversed = code.reverse_bits() >> (32 - u32::from(len));
+        self.put(reversed, len);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.acc as u8);
+            self.acc = 0;
+            self.count = 0;
+        }
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    lazy_static! {
+        static ref TABLE: [u32; 256] = {
+            let mut table = [0; 256];
+            for (n, entry) in table.iter_mut().enumerate() {
+                let mut c = n as u32;
+                for _ in 0..8 {
+                    c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+                }
+                *entry = c;
+            }
+            table
+        };
+    }
+
+    !data
+        .iter()
+        .fold(!0u32, |crc, &byte| TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes gzip members made of one fixed Huffman block each, as `compress` writes them,
+    /// checking each member's CRC and size.
+    fn gunzip(mut data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        while !data.is_empty() {
+            assert_eq!(&data[..4], &[0x1f, 0x8b, 8, 0], "gzip header");
+            let mut bits = BitReader { data: &data[10..], pos: 0 };
+            assert_eq!(bits.get(1), 1, "final block");
+            assert_eq!(bits.get(2), 1, "fixed Huffman codes");
+
+            let start = out.len();
+            loop {
+                let symbol = bits.literal();
+                match symbol {
+                    0..=255 => out.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let code = usize::from(symbol - 257);
+                        let len = usize::from(LENGTH_BASE[code]) + bits.get(LENGTH_EXTRA[code]) as usize;
+                        let code = bits.code(5) as usize;
+                        let distance = usize::from(DISTANCE_BASE[code]) + bits.get(DISTANCE_EXTRA[code]) as usize;
+                        assert!(distance <= out.len() - start, "distance beyond the member");
+                        for _ in 0..len {
+                            out.push(out[out.len() - distance]);
+                        }
+                    }
+                }
+            }
+
+            let rest = &bits.data[bits.pos.div_ceil(8)..];
+            let member = &out[start..];
+            assert_eq!(u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]), crc32(member));
+            assert_eq!(u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]), member.len() as u32);
+            data = &rest[8..];
+        }
+        out
+    }
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+            self.pos += 1;
+            u32::from(bit)
+        }
+
+        fn get(&mut self, len: u8) -> u32 {
+            (0..len).fold(0, |value, bit| value | self.bit() << bit)
+        }
+
+        fn code(&mut self, len: u8) -> u32 {
+            (0..len).fold(0, |code, _| code << 1 | self.bit())
+        }
+
+        fn literal(&mut self) -> u16 {
+            let mut code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code as u16;
+            }
+            code = code << 1 | self.bit();
+            match code {
+                0x30..=0xbf => (code - 0x30) as u16,
+                0xc0..=0xc7 => (280 + code - 0xc0) as u16,
+                _ => (144 + (code << 1 | self.bit()) - 0x190) as u16,
+            }
+        }
+    }
+
+    fn round_trip(data: &[u8]) {
+        assert_eq!(gunzip(&compress(data)), data);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn empty_input() {
+        round_trip(b"");
+    }
+
+    #[test]
+    fn literals_and_short_matches() {
+        round_trip(b"a");
+        round_trip(b"abcabcabc");
+        round_trip("$ ls -l\r\ntotal 0\r\n\u{1b}[01;34mdir\u{1b}[0m\r\n".as_bytes());
+    }
+
+    #[test]
+    fn longest_matches() {
+        round_trip(&[b'x'; 258 + 1]);
+        round_trip(&[b'x'; 258 * 3 + 7]);
+        let line: Vec<u8> = (0..=255).collect();
+        round_trip(&line.repeat(4));
+    }
+
+    #[test]
+    fn input_past_the_window() {
+        // Pseudo-random bytes from a small alphabet, so matches are found at all distances.
+        let mut seed = 1u32;
+        let data: Vec<u8> = (0..100_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                b"abcdefgh"[(seed >> 16) as usize % 8]
+            })
+            .collect();
+        round_trip(&data);
+
+        let mut repeated = data[..40_000].to_vec();
+        repeated.extend_from_slice(&data[..40_000]);
+        round_trip(&repeated);
+    }
+
+    #[test]
+    fn writer_finishes_the_last_member_on_drop() {
+        let data: Vec<u8> = (0..MEMBER_SIZE + 1000).map(|i| (i % 251) as u8).collect();
+        let mut out = Vec::new();
+        {
+            let mut gzip = GzipWriter::new(&mut out);
+            gzip.write_all(&data).unwrap();
+        }
+        assert_eq!(gunzip(&out), data);
+    }
+}
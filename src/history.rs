@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::policy::CommandLines;
+
+/// A command entered in the session, and whether the shell history has it.
+pub struct Entry {
+    pub command: String,
+    pub in_history: bool,
+}
+
+/// Reads the command lines typed in a session from an input log written with `--log-in`.
+pub fn typed_commands(input_log: &Path) -> io::Result<Vec<String>> {
+    Ok(CommandLines::new().feed(&fs::read(input_log)?))
+}
+
+/// Reads a bash or zsh history file, skipping bash's timestamp comments and the metadata of
+/// zsh's extended history.
+pub fn load(path: &Path) -> io::Result<Vec<String>> {
+    let data = fs::read(path)?;
+    Ok(String::from_utf8_lossy(&data)
+        .lines()
+        .filter(|line| !(line.starts_with('#') && line[1..].chars().all(|c| c.is_ascii_digit())))
+        .map(|line| match line.strip_prefix(": ").and_then(|rest| rest.split_once(';')) {
+            Some((_, command)) => command,
+            None => line,
+        })
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// Lines the typed commands up with the history, working back from the end since the session
+/// is usually the last one written to it. A typed command that the history doesn't have
+/// before the next command's match was kept out of it: by a leading space under
+/// `HISTCONTROL=ignorespace`, an unset `HISTFILE` or an edited history file.
+pub fn correlate(typed: &[String], history: &[String]) -> Vec<Entry> {
+    let mut end = history.len();
+    let mut entries: Vec<Entry> = typed
+        .iter()
+        .rev()
+        .map(|command| {
+            let found = history[..end].iter().rposition(|h| h == command);
+            if let Some(i) = found {
+                end = i;
+            }
+            Entry {
+                command: command.clone(),
+                in_history: found.is_some(),
+            }
+        })
+        .collect();
+    entries.reverse();
+    entries
+}
@@ -0,0 +1,36 @@
+//! Records terminal sessions like script(1).
+//!
+//! A [`Session`] runs a shell on a new pty and records everything it prints, in one of the
+//! [`Format`]s, while relaying the session to the terminal it was started from.
+
+#[macro_use]
+extern crate lazy_static;
+
+mod broadcast;
+mod clock;
+mod error;
+pub mod format;
+mod fswatch;
+mod gzip;
+pub mod history;
+mod mirror;
+pub mod plain;
+pub mod policy;
+pub mod prefix;
+mod proctree;
+mod pty;
+pub mod redact;
+pub mod replay;
+mod sampler;
+pub mod scrub;
+mod session;
+mod stamp;
+pub mod siem;
+mod systemd;
+mod timing;
+pub mod tty;
+pub mod usage;
+
+pub use error::{Error, Result};
+pub use format::Format;
+pub use session::{Compression, Session, WriteErrorPolicy};
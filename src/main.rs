@@ -1,195 +1,521 @@
 extern crate structopt;
 use structopt::StructOpt;
+use std::fs::File;
+use std::io::{self, Read, Write};
 use std::path::PathBuf;
 use std::path::Path;
+use std::time::Duration;
 
-#[macro_use]
-extern crate lazy_static;
-
-use nix::fcntl::{open, OFlag};
-use nix::libc::{atexit, winsize, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
-use nix::pty::*;
-use nix::sys::select::{select, FdSet};
-use nix::sys::stat::Mode;
-use nix::sys::termios::*;
-use nix::unistd::*;
-use nix::Result;
-use std::ffi::CString;
-use std::os::unix::prelude::*;
-
-use std::sync::Mutex;
+use nix::libc::STDIN_FILENO;
+use nix::unistd::isatty;
 
+use script_rs::plain::PlainText;
+use script_rs::policy::DenyAction;
+use script_rs::prefix::PrefixKey;
+use script_rs::redact::Redactor;
+use script_rs::siem::SiemFormat;
+use script_rs::{history, replay, scrub, tty, Compression, Format, Session, WriteErrorPolicy};
 
 #[derive(StructOpt)]
 struct Opt {
     /// Output file, typescript if not present
     #[structopt(parse(from_os_str))]
     pub output: Option<PathBuf>,
+
+    /// Append to the output (and timing) file instead of overwriting it
+    #[structopt(short = "a", long = "append")]
+    pub append: bool,
+
+    /// Run this command with $SHELL -c instead of an interactive shell, and exit with its status
+    #[structopt(short = "c", long = "command")]
+    pub command: Option<String>,
+
+    /// Exit with the exit status of the child
+    #[structopt(short = "e", long = "return")]
+    pub return_status: bool,
+
+    /// Don't write the start and done lines to the output file or the terminal
+    #[structopt(short = "q", long = "quiet")]
+    pub quiet: bool,
+
+    /// Recording format (script, asciicast, ttyrec, plain)
+    #[structopt(
+        long = "format",
+        default_value = "script",
+        raw(possible_values = r#"&["script", "asciicast", "ttyrec", "plain"]"#)
+    )]
+    pub format: Format,
+
+    /// Record plain text without escape sequences, same as --format plain
+    #[structopt(long = "plain")]
+    pub plain: bool,
+
+    /// Write timing data for scriptreplay to this file
+    #[structopt(short = "t", long = "timing", parse(from_os_str))]
+    pub timing: Option<PathBuf>,
+
+    /// Also log the input to this file
+    #[structopt(short = "I", long = "log-in", parse(from_os_str))]
+    pub log_in: Option<PathBuf>,
+
+    /// Log both input and output to this file
+    #[structopt(short = "B", long = "log-io", parse(from_os_str))]
+    pub log_io: Option<PathBuf>,
+
+    /// Allow --log-in and --log-io, whose logs hold everything typed, passwords included
+    #[structopt(long = "i-understand-input-logging-risks")]
+    pub input_logging_acknowledged: bool,
+
+    /// What to do when the output file runs out of space (abort, pause-recording, drop)
+    #[structopt(
+        long = "on-write-error",
+        default_value = "abort",
+        raw(possible_values = r#"&["abort", "pause-recording", "drop"]"#)
+    )]
+    pub on_write_error: WriteErrorPolicy,
+
+    /// Prefix key for recorder commands, as C-<key>, ^<key> or none (p: pause, n: new file, q:
+    /// stop, m: marker)
+    #[structopt(long = "prefix-key", default_value = "C-a")]
+    pub prefix_key: PrefixKey,
+
+    /// Keep an up-to-date plain text copy of the session in this file
+    #[structopt(long = "mirror-text", parse(from_os_str))]
+    pub mirror_text: Option<PathBuf>,
+
+    /// Keep recording for this many seconds after the terminal hangs up
+    #[structopt(long = "linger")]
+    pub linger: Option<u64>,
+
+    /// Show the elapsed time and output size in the terminal title
+    #[structopt(long = "title-status")]
+    pub title_status: bool,
+
+    /// Embed the wall clock time into the recording every this many seconds
+    #[structopt(long = "anchor-interval")]
+    pub anchor_interval: Option<u64>,
+
+    /// Flush the output file after each write, for following it live
+    #[structopt(short = "f", long = "flush")]
+    pub flush: bool,
+
+    /// Record only output, through a large lazily flushed buffer, for always-on recording
+    #[structopt(long = "low-overhead")]
+    pub low_overhead: bool,
+
+    /// Record at most this many bytes of output per second, dropping the rest
+    #[structopt(long = "max-rate")]
+    pub max_rate: Option<u64>,
+
+    /// Stop recording once the output file has grown by this many bytes (K, M and G suffixes
+    /// allowed)
+    #[structopt(long = "max-size", parse(try_from_str = "parse_size"))]
+    pub max_size: Option<u64>,
+
+    /// Also stop the session when the output file reaches --max-size
+    #[structopt(long = "kill-at-max-size")]
+    pub kill_at_max_size: bool,
+
+    /// Move the output file aside and start a new one once it has grown by this many bytes (K,
+    /// M and G suffixes allowed)
+    #[structopt(long = "rotate-size", parse(try_from_str = "parse_size"))]
+    pub rotate_size: Option<u64>,
+
+    /// Keep only this many rotated output files
+    #[structopt(long = "rotate-keep")]
+    pub rotate_keep: Option<usize>,
+
+    /// Compress the output file as it is written (gzip)
+    #[structopt(long = "compress")]
+    pub compress: Option<Compression>,
+
+    /// Stream the session live to read-only viewers connecting to this Unix socket
+    #[structopt(long = "broadcast", parse(from_os_str))]
+    pub broadcast: Option<PathBuf>,
+
+    /// Prefix each line in the typescript with the time
+    #[structopt(long = "timestamp")]
+    pub timestamp: bool,
+
+    /// strftime(3) format for --timestamp
+    #[structopt(long = "timestamp-format", default_value = "[%Y-%m-%d %H:%M:%S] ")]
+    pub timestamp_format: String,
+
+    /// Set an environment variable for the shell, as KEY=VALUE (can be repeated)
+    #[structopt(long = "env", parse(try_from_str = "parse_env"))]
+    pub env: Vec<(String, String)>,
+
+    /// Remove an environment variable from the shell's environment (can be repeated)
+    #[structopt(long = "unset")]
+    pub unset: Vec<String>,
+
+    /// Start the shell with only HOME, LOGNAME, PATH, SHELL, TERM and USER from the
+    /// environment, plus --env
+    #[structopt(long = "clean-env")]
+    pub clean_env: bool,
+
+    /// Journal file changes below this directory into the recording (can be repeated)
+    #[structopt(long = "watch", parse(from_os_str))]
+    pub watch: Vec<PathBuf>,
+
+    /// Check each command line entered against the patterns in this file
+    #[structopt(long = "deny", parse(from_os_str))]
+    pub deny: Option<PathBuf>,
+
+    /// What to do when a command matches --deny (warn, annotate, kill)
+    #[structopt(
+        long = "deny-action",
+        default_value = "warn",
+        raw(possible_values = r#"&["warn", "annotate", "kill"]"#)
+    )]
+    pub deny_action: DenyAction,
+
+    /// Record the process tree of the session along with each marker
+    #[structopt(long = "snapshot-procs")]
+    pub snapshot_procs: bool,
+
+    /// Write dates in UTC instead of local time
+    #[structopt(long = "utc")]
+    pub utc: bool,
+
+    /// Log session start and end events for a SIEM to this file, or to syslog if "syslog"
+    #[structopt(long = "siem", parse(from_os_str))]
+    pub siem: Option<PathBuf>,
+
+    /// Event format for --siem (cef, leef)
+    #[structopt(long = "siem-format", default_value = "cef", raw(possible_values = r#"&["cef", "leef"]"#))]
+    pub siem_format: SiemFormat,
+
+    /// Program to run instead of $SHELL
+    #[structopt(long = "shell")]
+    pub shell: Option<String>,
+
+    /// Keep the terminal size from the start, ignoring resizes
+    #[structopt(long = "freeze-size")]
+    pub freeze_size: bool,
+
+    /// Record even inside a session that is already being recorded
+    #[structopt(long = "force")]
+    pub force: bool,
+
+    /// Start the shell as a login shell
+    #[structopt(short = "l", long = "login")]
+    pub login: bool,
+
+    /// Extra arguments for the shell, after --
+    #[structopt(raw(last = "true"))]
+    pub shell_args: Vec<String>,
+
+    #[structopt(subcommand)]
+    pub cmd: Option<Subcommand>,
 }
 
-lazy_static! {
-    static ref TERMIOS: Mutex<Termios> = Mutex::new(tcgetattr(STDIN_FILENO).expect("can not get stdin tty"));
+#[derive(StructOpt)]
+enum Subcommand {
+    /// Replay a typescript with the pacing from its timing file
+    #[structopt(name = "replay")]
+    Replay {
+        /// Typescript to replay
+        #[structopt(parse(from_os_str))]
+        typescript: PathBuf,
+
+        /// Timing file written with --timing
+        #[structopt(parse(from_os_str))]
+        timing: PathBuf,
+
+        /// Playback speed, 2.0 plays twice as fast
+        #[structopt(short = "s", long = "speed", default_value = "1.0")]
+        speed: f64,
+
+        /// Mask text matching the rules in this file
+        #[structopt(long = "redact", parse(from_os_str))]
+        redact: Option<PathBuf>,
+    },
+
+    /// List the commands typed in a session and flag those missing from the shell history
+    #[structopt(name = "analyze")]
+    Analyze {
+        /// Input log written with --log-in
+        #[structopt(parse(from_os_str))]
+        input_log: PathBuf,
+
+        /// Shell history file to check against, e.g. ~/.bash_history
+        #[structopt(long = "with-history", parse(from_os_str))]
+        history: PathBuf,
+    },
+
+    /// Write the commands typed in a session out as a shell script
+    #[structopt(name = "export")]
+    Export {
+        /// Input log written with --log-in
+        #[structopt(parse(from_os_str))]
+        input_log: PathBuf,
+
+        /// Script language (sh)
+        #[structopt(long = "to", default_value = "sh", raw(possible_values = r#"&["sh"]"#))]
+        to: String,
+    },
+
+    /// Print a typescript as plain text, without escape sequences
+    #[structopt(name = "clean")]
+    Clean {
+        /// Typescript to clean up
+        #[structopt(parse(from_os_str))]
+        typescript: PathBuf,
+    },
+
+    /// Remove the input from a timing file before sharing the recording; --log-in and --log-io
+    /// files hold the input themselves and are not to be shared
+    #[structopt(name = "scrub-input")]
+    ScrubInput {
+        /// Timing file written with --timing
+        #[structopt(parse(from_os_str))]
+        timing: PathBuf,
+    },
 }
 
 fn main() {
     let opt = Opt::from_args();
 
-    let mut ws = winsize {
-        ws_row: 0,
-        ws_col: 0,
-        ws_xpixel: 0,
-        ws_ypixel: 0,
-    };
-
-    unsafe { ioctl::tiocgwinsz(STDIN_FILENO, &mut ws) }.expect("can not ge stdin window size");
-
-    let mut master_fd = None;
-    let mut slave_name = None;
-
-    let fork_result = match pty_fork(&mut master_fd, &mut slave_name, Some(&*TERMIOS.lock().unwrap()), ws) {
-        Ok(result) => result,
-        Err(e) => panic!("{:?}", e),
-    };
-
-    if fork_result.is_child() {
-        match std::env::var("SHELL") {
-            Ok(shell) => {
-                let shell = CString::new(shell.as_str()).unwrap();
-                execv(&shell, &[]).expect("can not exec shell");
-            },
-            Err(_) => {
-                let shell = CString::new("/bin/sh").unwrap();
-                execv(&shell, &[]).expect("can not exec shell");
+    match opt.cmd {
+        Some(Subcommand::Replay { typescript, timing, speed, redact }) => {
+            run_replay(&typescript, &timing, speed, redact.as_deref());
+            return;
+        }
+        Some(Subcommand::Analyze { input_log, history }) => {
+            run_analyze(&input_log, &history);
+            return;
+        }
+        Some(Subcommand::Export { input_log, to }) => {
+            run_export(&input_log, &to);
+            return;
+        }
+        Some(Subcommand::Clean { typescript }) => {
+            if let Err(e) = run_clean(&typescript) {
+                eprintln!("script-rs: {}: {}", typescript.display(), e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Subcommand::ScrubInput { timing }) => {
+            match scrub::scrub_input(&timing) {
+                Ok(removed) => println!("Removed {} input entries from {}.", removed, timing.display()),
+                Err(e) => {
+                    eprintln!("script-rs: {}: {}", timing.display(), e);
+                    std::process::exit(1);
+                }
             }
+            return;
         }
+        None => {}
     }
 
-    let master_fd = match master_fd {
-        Some(fd) => fd,
-        None => panic!("master fd is not found"),
+    let mut session = Session::new()
+        .output(opt.output.clone().unwrap_or_else(|| {
+            PathBuf::from(if opt.compress.is_some() { "typescript.gz" } else { "typescript" })
+        }))
+        .append(opt.append)
+        .quiet(opt.quiet)
+        .format(if opt.plain { Format::Plain } else { opt.format })
+        .on_write_error(opt.on_write_error)
+        .prefix_key(opt.prefix_key)
+        .title_status(opt.title_status)
+        .utc(opt.utc)
+        .flush(opt.flush)
+        .low_overhead(opt.low_overhead)
+        .snapshot_processes(opt.snapshot_procs)
+        .kill_at_max_size(opt.kill_at_max_size)
+        .env_clear(opt.clean_env)
+        .login(opt.login)
+        .force(opt.force)
+        .freeze_size(opt.freeze_size)
+        .input_logging_acknowledged(opt.input_logging_acknowledged);
+    for (key, value) in &opt.env {
+        session = session.env(key.as_str(), value.as_str());
+    }
+    if let Some(ref shell) = opt.shell {
+        session = session.shell(shell.as_str());
+    }
+    for arg in &opt.shell_args {
+        session = session.arg(arg.as_str());
+    }
+    for key in &opt.unset {
+        session = session.env_remove(key.as_str());
+    }
+    if let Some(ref command) = opt.command {
+        session = session.command(command.as_str());
+    }
+    if let Some(ref path) = opt.timing {
+        session = session.timing(path);
+    }
+    if let Some(ref path) = opt.log_in {
+        session = session.log_in(path);
+    }
+    if let Some(ref path) = opt.log_io {
+        session = session.log_io(path);
+    }
+    if let Some(ref path) = opt.mirror_text {
+        session = session.mirror_text(path);
+    }
+    if let Some(secs) = opt.linger {
+        session = session.linger(Duration::from_secs(secs));
+    }
+    if let Some(secs) = opt.anchor_interval {
+        session = session.anchor_interval(Duration::from_secs(secs));
+    }
+    if let Some(rate) = opt.max_rate {
+        session = session.max_rate(rate);
+    }
+    for dir in &opt.watch {
+        session = session.watch(dir);
+    }
+    if let Some(bytes) = opt.max_size {
+        session = session.max_size(bytes);
+    }
+    if let Some(bytes) = opt.rotate_size {
+        session = session.rotate_size(bytes);
+    }
+    if let Some(files) = opt.rotate_keep {
+        session = session.rotate_keep(files);
+    }
+    if let Some(compression) = opt.compress {
+        session = session.compress(compression);
+    }
+    if opt.timestamp {
+        session = session.timestamp(opt.timestamp_format.as_str());
+    }
+    if let Some(ref path) = opt.broadcast {
+        session = session.broadcast(path);
+    }
+    if let Some(ref path) = opt.deny {
+        session = session.deny(path, opt.deny_action);
+    }
+    if let Some(ref target) = opt.siem {
+        session = session.siem(target, opt.siem_format);
+    }
+    let status = match session.spawn() {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("script-rs: {}", e);
+            std::process::exit(1);
+        }
     };
 
-    let out_path = opt.output.unwrap_or_else(|| PathBuf::from("typescript"));
-
-    let script_fd = open(
-        out_path.as_path(),
-        OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC,
-        Mode::S_IRUSR
-            | Mode::S_IWUSR
-            | Mode::S_IRGRP
-            | Mode::S_IWGRP
-            | Mode::S_IROTH
-            | Mode::S_IWOTH,
-    )
-    .expect("script_fd");
-    tty_set_row(STDIN_FILENO, &mut *TERMIOS.lock().unwrap());
-    unsafe { atexit(reset_tty) };
+    if opt.command.is_some() || opt.return_status {
+        std::process::exit(status);
+    }
+}
 
-    loop {
-        let mut buf: [u8; 256] = [0; 256];
-        let mut in_fds = FdSet::new();
-        in_fds.insert(STDIN_FILENO);
-        in_fds.insert(master_fd);
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("expected KEY=VALUE: {}", s)),
+    }
+}
 
-        select(Some(master_fd + 1), Some(&mut in_fds), None, None, None).unwrap();
+/// Parses a size in bytes with an optional binary K, M or G suffix, e.g. `100M`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, unit) = match s.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => s.split_at(i),
+        None => (s, ""),
+    };
+    let shift = match unit {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return Err(format!("invalid size: {}", s)),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size: {}", s))
+}
 
-        if in_fds.contains(STDIN_FILENO) {
-            if read(STDIN_FILENO, &mut buf).is_err() {
-                return;
-            }
-            write(master_fd, &buf).unwrap();
+fn run_analyze(input_log: &Path, history: &Path) {
+    let read = |path: &Path, result: io::Result<Vec<String>>| match result {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("script-rs: {}: {}", path.display(), e);
+            std::process::exit(1);
         }
+    };
+    let typed = read(input_log, history::typed_commands(input_log));
+    let history = read(history, history::load(history));
 
-        if in_fds.contains(master_fd) {
-            if read(master_fd, &mut buf).is_err() {
-                return;
-            }
-            write(STDOUT_FILENO, &buf).unwrap();
-            write(script_fd, &buf).unwrap();
-        }
+    let entries = history::correlate(&typed, &history);
+    for entry in &entries {
+        println!("{} {}", if entry.in_history { "     " } else { "MISSING" }, entry.command);
     }
+    let missing = entries.iter().filter(|entry| !entry.in_history).count();
+    println!("{} commands, {} missing from the history", entries.len(), missing);
 }
 
-fn pty_master_open() -> Result<(nix::pty::PtyMaster, String)> {
-    let master_fd = posix_openpt(OFlag::O_RDWR)?;
-    grantpt(&master_fd)?;
-    unlockpt(&master_fd)?;
+fn run_export(input_log: &Path, shell: &str) {
+    let commands = match history::typed_commands(input_log) {
+        Ok(commands) => commands,
+        Err(e) => {
+            eprintln!("script-rs: {}: {}", input_log.display(), e);
+            std::process::exit(1);
+        }
+    };
 
-    // Get the name of the slave
-    let slave_name = unsafe { ptsname(&master_fd) }?;
-    Ok((master_fd, slave_name))
+    println!("#!/bin/{}", shell);
+    println!("# Commands typed in {}, exported by script-rs.", input_log.display());
+    println!("# Review before running: lines typed into programs other than the shell are");
+    println!("# included too.");
+    for command in commands {
+        println!("{}", command);
+    }
 }
 
-fn pty_fork(
-    master_fd: &mut Option<RawFd>,
-    slave_name: &mut Option<String>,
-    slave_termios: Option<&Termios>,
-    slave_win_size: winsize,
-) -> Result<ForkResult> {
-    // Open pty master
-    let (mfd, slname) = pty_master_open()?;
+fn run_clean(typescript: &Path) -> io::Result<()> {
+    let mut input = File::open(typescript)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut text = PlainText::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        let mut lines = String::new();
+        text.feed(&buf[..n], &mut lines);
+        stdout.write_all(lines.as_bytes())?;
+    }
+    stdout.write_all(text.current_line().as_bytes())
+}
 
-    if slave_name.is_some() {
-        *slave_name = Some(slname.clone());
+fn run_replay(typescript: &Path, timing: &Path, speed: f64, redact: Option<&Path>) {
+    if speed <= 0.0 || !speed.is_finite() {
+        eprintln!("script-rs: invalid speed: {}", speed);
+        std::process::exit(1);
     }
 
-    // Fork process
-    match fork() {
-        Ok(ForkResult::Parent { child }) => {
-            *master_fd = Some(mfd.into_raw_fd());
-            Ok(ForkResult::Parent { child })
+    let redactor = match redact.map(Redactor::load).transpose() {
+        Ok(redactor) => redactor,
+        Err(e) => {
+            eprintln!("script-rs: {}", e);
+            std::process::exit(1);
         }
-        Ok(ForkResult::Child) => {
-            // Set session id to child process
-            setsid().unwrap();
-            close(mfd.into_raw_fd())?;
-
-            let slave_fd = open(Path::new(&slname), OFlag::O_RDWR, Mode::empty())?;
-
-            // For BSD
-            if cfg!(target_os = "openbsd") {
-                unsafe { ioctl::tiocsctty(0, &slave_fd) }.unwrap();
-            }
-
-            if slave_termios.is_some() {
-                tcsetattr(slave_fd, SetArg::TCSANOW, &slave_termios.unwrap())?;
-            }
-
-            tcsetattr(STDIN_FILENO, SetArg::TCSAFLUSH, &slave_termios.unwrap())?;
-            unsafe { ioctl::tiocswinsz(slave_fd, &slave_win_size) }?;
-
-            dup2(slave_fd, STDIN_FILENO)?;
-            dup2(slave_fd, STDOUT_FILENO)?;
-            dup2(slave_fd, STDERR_FILENO)?;
-
-            if slave_fd > STDERR_FILENO {
-                close(slave_fd)?;
-            }
+    };
 
-            Ok(ForkResult::Child)
-        }
-        Err(err) => {
-            close(mfd.into_raw_fd())?;
-            panic!("{:?}", err);
+    // Keys control the playback, so only read them from a terminal.
+    let interactive = isatty(STDIN_FILENO).unwrap_or(false);
+    if interactive {
+        if let Err(e) = tty::enter_raw_mode() {
+            eprintln!("script-rs: {}", e);
+            std::process::exit(1);
         }
     }
-}
-
-fn tty_set_row(fd: i32, prev_termios: &mut Termios) {
-    *prev_termios = tcgetattr(fd).unwrap().clone();
-    let mut termios = tcgetattr(fd).unwrap();
-    cfmakeraw(&mut termios);
-    tcsetattr(fd, SetArg::TCSAFLUSH, &termios).unwrap();
-}
 
-extern "C" fn reset_tty() {
-    tcsetattr(STDIN_FILENO, SetArg::TCSANOW, &TERMIOS.lock().unwrap()).unwrap()
-}
-
-mod ioctl {
-    use nix::libc::{winsize, TIOCGWINSZ, TIOCSWINSZ, TIOCSCTTY};
-    use nix::*;
-    ioctl_write_ptr_bad!(tiocswinsz, TIOCSWINSZ, winsize);
-    ioctl_read_bad!(tiocgwinsz, TIOCGWINSZ, winsize);
-    ioctl_write_ptr_bad!(tiocsctty, TIOCSCTTY, i32);
+    if let Err(e) = replay::replay(typescript, timing, speed, interactive, redactor) {
+        if interactive {
+            tty::reset_tty();
+        }
+        eprintln!("script-rs: {}", e);
+        std::process::exit(1);
+    }
 }
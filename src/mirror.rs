@@ -0,0 +1,36 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::plain::PlainText;
+
+/// Keeps a plain text copy of the session up to date on disk. Finished lines are appended,
+/// and the line still being written is rewritten in place after them.
+pub struct TextMirror {
+    file: File,
+    text: PlainText,
+    committed: u64,
+}
+
+impl TextMirror {
+    pub fn create(path: &Path) -> io::Result<TextMirror> {
+        Ok(TextMirror {
+            file: File::create(path)?,
+            text: PlainText::new(),
+            committed: 0,
+        })
+    }
+
+    pub fn write(&mut self, output: &[u8]) -> io::Result<()> {
+        let mut lines = String::new();
+        self.text.feed(output, &mut lines);
+
+        self.file.seek(SeekFrom::Start(self.committed))?;
+        self.file.write_all(lines.as_bytes())?;
+        self.committed += lines.len() as u64;
+
+        let current = self.text.current_line();
+        self.file.write_all(current.as_bytes())?;
+        self.file.set_len(self.committed + current.len() as u64)
+    }
+}
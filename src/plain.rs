@@ -0,0 +1,227 @@
+/// Strips escape sequences from terminal output and resolves carriage returns, backspaces and
+/// line erasures into plain text lines.
+pub struct PlainText {
+    state: State,
+    params: Vec<u8>,
+    utf8: Vec<u8>,
+    line: Vec<char>,
+    col: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    Charset,
+    Csi,
+    Osc,
+    OscEscape,
+    Str,
+    StrEscape,
+}
+
+const TAB_WIDTH: usize = 8;
+
+/// Widest line cursor movements can reach. The counts come from the recorded output, so they
+/// must not be able to make a line arbitrarily long.
+const MAX_COLUMNS: usize = 4096;
+
+impl Default for PlainText {
+    fn default() -> PlainText {
+        PlainText::new()
+    }
+}
+
+impl PlainText {
+    pub fn new() -> PlainText {
+        PlainText {
+            state: State::Ground,
+            params: Vec::new(),
+            utf8: Vec::new(),
+            line: Vec::new(),
+            col: 0,
+        }
+    }
+
+    /// Feeds output bytes, appending each finished line (with its newline) to `out`.
+    pub fn feed(&mut self, input: &[u8], out: &mut String) {
+        for &b in input {
+            match self.state {
+                State::Ground => self.ground(b, out),
+                State::Escape => {
+                    self.state = match b {
+                        b'[' => {
+                            self.params.clear();
+                            State::Csi
+                        }
+                        b']' => State::Osc,
+                        b'P' | b'X' | b'^' | b'_' => State::Str,
+                        b'(' | b')' | b'*' | b'+' | b'#' | b'%' => State::Charset,
+                        _ => State::Ground,
+                    }
+                }
+                State::Charset => self.state = State::Ground,
+                State::Csi => match b {
+                    0x40..=0x7e => {
+                        self.csi(b);
+                        self.state = State::Ground;
+                    }
+                    0x18 | 0x1a => self.state = State::Ground,
+                    0x1b => self.state = State::Escape,
+                    _ => self.params.push(b),
+                },
+                State::Osc => match b {
+                    0x07 => self.state = State::Ground,
+                    0x1b => self.state = State::OscEscape,
+                    _ => {}
+                },
+                State::Str => {
+                    if b == 0x1b {
+                        self.state = State::StrEscape;
+                    }
+                }
+                State::OscEscape | State::StrEscape => self.state = State::Ground,
+            }
+        }
+    }
+
+    /// Returns the line that is still being written.
+    pub fn current_line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    fn ground(&mut self, b: u8, out: &mut String) {
+        match b {
+            0x1b => self.state = State::Escape,
+            b'\n' => {
+                out.extend(self.line.drain(..));
+                out.push('\n');
+                self.col = 0;
+            }
+            b'\r' => self.col = 0,
+            0x08 => self.col = self.col.saturating_sub(1),
+            b'\t' => {
+                let stop = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.col < stop {
+                    self.put(' ');
+                }
+            }
+            0x00..=0x1f | 0x7f => {}
+            0x20..=0x7e => self.put(b as char),
+            _ => {
+                self.utf8.push(b);
+                match std::str::from_utf8(&self.utf8) {
+                    Ok(s) => {
+                        let c = s.chars().next().unwrap_or('\u{fffd}');
+                        self.utf8.clear();
+                        self.put(c);
+                    }
+                    Err(e) if e.error_len().is_some() || self.utf8.len() >= 4 => {
+                        self.utf8.clear();
+                        self.put('\u{fffd}');
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        if self.col < self.line.len() {
+            self.line[self.col] = c;
+        } else {
+            self.line.resize(self.col, ' ');
+            self.line.push(c);
+        }
+        self.col += 1;
+    }
+
+    fn csi(&mut self, action: u8) {
+        let first = std::str::from_utf8(&self.params)
+            .ok()
+            .and_then(|p| p.split(';').next())
+            .and_then(|p| p.parse::<usize>().ok());
+        let count = first.unwrap_or(1).clamp(1, MAX_COLUMNS);
+
+        match action {
+            b'K' => match first.unwrap_or(0) {
+                0 => self.line.truncate(self.col),
+                1 => {
+                    let end = (self.col + 1).min(self.line.len());
+                    self.line[..end].iter_mut().for_each(|c| *c = ' ');
+                }
+                _ => self.line.clear(),
+            },
+            b'C' => self.col = (self.col + count).min(MAX_COLUMNS.max(self.col)),
+            b'D' => self.col = self.col.saturating_sub(count),
+            b'G' => self.col = count - 1,
+            b'P' if self.col < self.line.len() => {
+                let end = (self.col + count).min(self.line.len());
+                self.line.drain(self.col..end);
+            }
+            b'@' if self.col < self.line.len() => {
+                // Like on a terminal, what is pushed off the edge is lost.
+                let width = MAX_COLUMNS.max(self.line.len());
+                self.line.splice(self.col..self.col, std::iter::repeat_n(' ', count));
+                self.line.truncate(width);
+            }
+            b'X' => {
+                let end = (self.col + count).min(self.line.len());
+                if self.col < end {
+                    self.line[self.col..end].iter_mut().for_each(|c| *c = ' ');
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(input: &[u8]) -> String {
+        let mut text = PlainText::new();
+        let mut out = String::new();
+        text.feed(input, &mut out);
+        out + &text.current_line()
+    }
+
+    #[test]
+    fn strips_colors_and_titles() {
+        assert_eq!(plain(b"\x1b[01;34mdir\x1b[0m\r\n"), "dir\n");
+        assert_eq!(plain(b"\x1b]0;user@host\x07$ ls\r\n"), "$ ls\n");
+    }
+
+    #[test]
+    fn cursor_movement() {
+        assert_eq!(plain(b"abc\x1b[2Dx\r\n"), "axc\n");
+        assert_eq!(plain(b"a\x1b[3Cb\r\n"), "a   b\n");
+        assert_eq!(plain(b"abcdef\x1b[3Gx\r\n"), "abxdef\n");
+        assert_eq!(plain(b"abc\rx\x08y\r\n"), "ybc\n");
+    }
+
+    #[test]
+    fn erasing_and_inserting() {
+        assert_eq!(plain(b"abcdef\x1b[3D\x1b[K\r\n"), "abc\n");
+        assert_eq!(plain(b"abcdef\x1b[3G\x1b[1K\r\n"), "   def\n");
+        assert_eq!(plain(b"abcdef\x1b[2K\r\n"), "\n");
+        assert_eq!(plain(b"abcdef\x1b[2G\x1b[2P\r\n"), "adef\n");
+        assert_eq!(plain(b"abc\x1b[2G\x1b[2@\r\n"), "a  bc\n");
+        assert_eq!(plain(b"abcdef\x1b[2G\x1b[2X\r\n"), "a  def\n");
+    }
+
+    #[test]
+    fn huge_counts_are_clamped() {
+        let line = plain(b"abc\x1b[3000000000Cx");
+        assert_eq!(line.len(), MAX_COLUMNS + 1);
+        assert!(line.ends_with(" x"));
+
+        let line = plain(b"abc\x1b[3000000000Gx");
+        assert_eq!(line.len(), MAX_COLUMNS);
+
+        let line = plain(b"abc\x1b[2G\x1b[3000000000@");
+        assert_eq!(line.len(), MAX_COLUMNS);
+        assert!(line.starts_with("a "));
+    }
+}
@@ -0,0 +1,236 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// What happens when a denied command is entered.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DenyAction {
+    /// Tell the user on the terminal.
+    Warn,
+    /// Also add a `DENIED` information entry to the recording, which needs the asciicast
+    /// format or a timing file.
+    Annotate,
+    /// Also end the session.
+    Kill,
+}
+
+impl FromStr for DenyAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(DenyAction::Warn),
+            "annotate" => Ok(DenyAction::Annotate),
+            "kill" => Ok(DenyAction::Kill),
+            _ => Err(format!("unknown deny action: {}", s)),
+        }
+    }
+}
+
+/// Patterns for commands that must not be run in an audited session.
+///
+/// A patterns file has one pattern per line; empty lines and lines starting with `#` are
+/// ignored. A pattern matches a command line that contains it, where `*` stands for any run
+/// of characters and `?` for any one character:
+///
+/// ```text
+/// rm -rf /
+/// curl * | sh
+/// ```
+pub struct DenyList {
+    patterns: Vec<String>,
+}
+
+impl DenyList {
+    pub fn load(path: &Path) -> io::Result<DenyList> {
+        let patterns = fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect();
+        Ok(DenyList { patterns })
+    }
+
+    /// Returns the first pattern matching `line`.
+    pub fn check(&self, line: &str) -> Option<&str> {
+        let line: Vec<char> = line.chars().collect();
+        self.patterns
+            .iter()
+            .find(|pattern| {
+                let pattern: Vec<char> = pattern.chars().collect();
+                contains(&pattern, &line)
+            })
+            .map(String::as_str)
+    }
+}
+
+/// Whether `pattern` matches anywhere in `text`, as if it started and ended with `*`.
+///
+/// This is the usual iterative glob match: on a mismatch it goes back to the last `*` and lets
+/// it take one more character, which keeps it O(n·m) however many stars the pattern has.
+fn contains(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The pattern position after the last `*` and the text position it was tried at; the
+    // implicit leading `*` starts it at the beginning.
+    let (mut star_p, mut star_t) = (0, 0);
+    while p < pattern.len() {
+        match pattern[p] {
+            '*' => {
+                p += 1;
+                star_p = p;
+                star_t = t;
+            }
+            c if t < text.len() && (c == '?' || c == text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ if star_t < text.len() => {
+                star_t += 1;
+                p = star_p;
+                t = star_t;
+            }
+            _ => return false,
+        }
+    }
+    // The implicit trailing `*` takes whatever text is left.
+    true
+}
+
+/// Puts typed input back together into the command lines that are entered, following the
+/// basic line editing keys. Cursor movement and completion done by the shell are not seen,
+/// so the result is what was typed rather than exactly what the shell ran.
+pub struct CommandLines {
+    line: Vec<u8>,
+    escape: Escape,
+}
+
+impl Default for CommandLines {
+    fn default() -> CommandLines {
+        CommandLines::new()
+    }
+}
+
+impl CommandLines {
+    pub fn new() -> CommandLines {
+        CommandLines {
+            line: Vec::new(),
+            escape: Escape::None,
+        }
+    }
+
+    /// Feeds typed input and returns the lines completed by it.
+    pub fn feed(&mut self, input: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for &byte in input {
+            // Cursor and function keys send escape sequences, which are skipped.
+            match self.escape {
+                Escape::None => {}
+                Escape::Start => {
+                    self.escape = match byte {
+                        b'[' => Escape::Csi,
+                        b'O' => Escape::Ss3,
+                        _ => Escape::None,
+                    };
+                    continue;
+                }
+                Escape::Csi => {
+                    if (0x40..=0x7e).contains(&byte) {
+                        self.escape = Escape::None;
+                    }
+                    continue;
+                }
+                Escape::Ss3 => {
+                    self.escape = Escape::None;
+                    continue;
+                }
+            }
+            match byte {
+                b'\r' | b'\n' => {
+                    let line = String::from_utf8_lossy(&self.line).trim().to_owned();
+                    if !line.is_empty() {
+                        lines.push(line);
+                    }
+                    self.line.clear();
+                }
+                // DEL and backspace remove a whole UTF-8 character.
+                0x7f | 0x08 => {
+                    while let Some(byte) = self.line.pop() {
+                        if byte & 0xc0 != 0x80 {
+                            break;
+                        }
+                    }
+                }
+                // ^U and ^C throw the line away.
+                0x15 | 0x03 => self.line.clear(),
+                0x1b => self.escape = Escape::Start,
+                0x00..=0x1f => {}
+                _ => self.line.push(byte),
+            }
+        }
+        lines
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Escape {
+    None,
+    Start,
+    Csi,
+    Ss3,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deny(patterns: &[&str]) -> DenyList {
+        DenyList {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn check_matches_anywhere_in_the_line() {
+        let list = deny(&["rm -rf /", "curl * | sh"]);
+        assert_eq!(list.check("sudo rm -rf / --no-preserve-root"), Some("rm -rf /"));
+        assert_eq!(list.check("curl https://example.com/install | sh"), Some("curl * | sh"));
+        assert_eq!(list.check("curl https://example.com/install -o install"), None);
+        assert_eq!(list.check("rm -rf ./build"), None);
+    }
+
+    #[test]
+    fn check_wildcards() {
+        let list = deny(&["a?c"]);
+        assert_eq!(list.check("xabcx"), Some("a?c"));
+        assert_eq!(list.check("ac"), None);
+        assert_eq!(deny(&["*"]).check(""), Some("*"));
+        assert_eq!(deny(&["a*"]).check("a"), Some("a*"));
+        assert_eq!(deny(&["a*b*c"]).check("aXbYcZ"), Some("a*b*c"));
+        assert_eq!(deny(&["a*b*c"]).check("aXcYb"), None);
+        assert_eq!(deny(&["é?"]).check("café!"), Some("é?"));
+    }
+
+    #[test]
+    fn check_many_stars_stays_fast() {
+        let line = format!("echo {}", "a ".repeat(500));
+        assert_eq!(deny(&["* * * * * * * * Z"]).check(&line), None);
+    }
+
+    #[test]
+    fn feed_splits_lines_and_follows_editing_keys() {
+        let mut lines = CommandLines::new();
+        assert_eq!(lines.feed(b"ls -l\rech"), vec!["ls -l"]);
+        assert_eq!(lines.feed(b"o hi\x7f\x7fho\n"), vec!["echo ho"]);
+        assert_eq!(lines.feed(b"secret\x15whoami\r"), vec!["whoami"]);
+        assert_eq!(lines.feed(b"sleep 9\x03\r  \r"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn feed_skips_escape_sequences() {
+        let mut lines = CommandLines::new();
+        assert_eq!(lines.feed(b"ab\x1b[Dc\x1bOA\x1b[1;5C\r"), vec!["abc"]);
+        assert_eq!(lines.feed("caf\u{e9}\x7fe\r".as_bytes()), vec!["cafe"]);
+    }
+}
@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+/// Recorder commands entered on stdin after the prefix key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    /// Toggle writing to the output file.
+    Pause,
+    /// Close the output file and continue in a new one.
+    Switch,
+    /// End the recording without waiting for the shell to exit.
+    Stop,
+    /// Insert a numbered marker into the recording.
+    Mark,
+}
+
+impl Command {
+    fn from_key(key: u8) -> Option<Command> {
+        match key {
+            b'p' => Some(Command::Pause),
+            b'n' => Some(Command::Switch),
+            b'q' => Some(Command::Stop),
+            b'm' => Some(Command::Mark),
+            _ => None,
+        }
+    }
+}
+
+/// A screen/tmux-style prefix key, given as `C-<key>`, `^<key>` or `none`.
+#[derive(Clone, Copy)]
+pub struct PrefixKey(Option<u8>);
+
+impl Default for PrefixKey {
+    /// Ctrl-A, like screen.
+    fn default() -> PrefixKey {
+        PrefixKey(Some(0x01))
+    }
+}
+
+impl PrefixKey {
+    /// No prefix key: all input goes to the child.
+    pub fn none() -> PrefixKey {
+        PrefixKey(None)
+    }
+}
+
+impl FromStr for PrefixKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(PrefixKey(None));
+        }
+
+        if let [b'C', b'-', key] | [b'^', key] = s.as_bytes() {
+            let key = key.to_ascii_uppercase();
+            if (b'@'..=b'_').contains(&key) {
+                return Ok(PrefixKey(Some(key & 0x1f)));
+            }
+        }
+
+        Err(format!("invalid prefix key: {} (expected C-<key>, ^<key> or none)", s))
+    }
+}
+
+/// Splits stdin input into bytes for the child and recorder commands.
+pub struct PrefixFilter {
+    key: Option<u8>,
+    pending: bool,
+}
+
+impl PrefixFilter {
+    pub fn new(key: PrefixKey) -> PrefixFilter {
+        PrefixFilter {
+            key: key.0,
+            pending: false,
+        }
+    }
+
+    /// Appends the bytes meant for the child to `forward` and returns the commands found in
+    /// `input`. The prefix pressed twice sends it through literally; unbound keys are swallowed.
+    pub fn feed(&mut self, input: &[u8], forward: &mut Vec<u8>) -> Vec<Command> {
+        let key = match self.key {
+            Some(key) => key,
+            None => {
+                forward.extend_from_slice(input);
+                return Vec::new();
+            }
+        };
+
+        let mut commands = Vec::new();
+        for &b in input {
+            if self.pending {
+                self.pending = false;
+                if b == key {
+                    forward.push(b);
+                } else if let Some(command) = Command::from_key(b) {
+                    commands.push(command);
+                }
+            } else if b == key {
+                self.pending = true;
+            } else {
+                forward.push(b);
+            }
+        }
+        commands
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::fs;
+
+use nix::unistd::Pid;
+
+/// A process in a snapshot of the session's process tree.
+pub struct Process {
+    pub pid: i32,
+    pub ppid: i32,
+    pub command: String,
+}
+
+/// Lists `root` and its descendants from /proc, each parent before its children. Empty where
+/// there is no /proc.
+pub fn snapshot(root: Pid) -> Vec<Process> {
+    let mut all = Vec::new();
+    if let Ok(entries) = fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let pid = match entry.file_name().to_str().and_then(|name| name.parse().ok()) {
+                Some(pid) => pid,
+                None => continue,
+            };
+            if let Some(process) = read_process(pid) {
+                all.push(process);
+            }
+        }
+    }
+
+    let mut children: HashMap<i32, Vec<usize>> = HashMap::new();
+    for (i, process) in all.iter().enumerate() {
+        children.entry(process.ppid).or_default().push(i);
+    }
+
+    let mut tree = Vec::new();
+    let mut stack: Vec<usize> = all.iter().position(|p| p.pid == i32::from(root)).into_iter().collect();
+    while let Some(i) = stack.pop() {
+        if let Some(kids) = children.get(&all[i].pid) {
+            stack.extend(kids.iter().rev());
+        }
+        tree.push(i);
+    }
+
+    let mut all: Vec<Option<Process>> = all.into_iter().map(Some).collect();
+    tree.into_iter().filter_map(|i| all[i].take()).collect()
+}
+
+/// Formats a snapshot as `pid(ppid) command` entries separated by `; `. Command lines are kept
+/// as they are, newlines included; like any information value, they are escaped where they are
+/// written to the timing file.
+pub fn format(processes: &[Process]) -> String {
+    processes
+        .iter()
+        .map(|p| format!("{}({}) {}", p.pid, p.ppid, p.command))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn read_process(pid: i32) -> Option<Process> {
+    // The command name in parentheses may itself contain spaces and parentheses.
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let comm = &stat[open + 1..close];
+    let ppid = stat[close + 1..].split_whitespace().nth(1)?.parse().ok()?;
+
+    // Kernel threads and zombies have no command line.
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let args: Vec<_> = cmdline
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect();
+    let command = if args.is_empty() {
+        format!("[{}]", comm)
+    } else {
+        args.join(" ")
+    };
+
+    Some(Process { pid, ppid, command })
+}
@@ -0,0 +1,94 @@
+use std::os::unix::prelude::*;
+use std::path::Path;
+
+use nix::fcntl::{open, OFlag};
+use nix::libc::{self, winsize, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use nix::pty::*;
+use nix::sys::stat::Mode;
+use nix::sys::termios::*;
+use nix::unistd::*;
+use nix::Result;
+
+use crate::error::Error;
+
+fn pty_master_open() -> Result<(nix::pty::PtyMaster, String)> {
+    let master_fd = posix_openpt(OFlag::O_RDWR)?;
+    grantpt(&master_fd)?;
+    unlockpt(&master_fd)?;
+
+    // Get the name of the slave
+    let slave_name = unsafe { ptsname(&master_fd) }?;
+    Ok((master_fd, slave_name))
+}
+
+pub(crate) fn pty_fork(
+    master_fd: &mut Option<RawFd>,
+    slave_name: &mut Option<String>,
+    slave_termios: Option<&Termios>,
+    slave_win_size: winsize,
+) -> crate::error::Result<ForkResult> {
+    // Open pty master
+    let (mfd, slname) = pty_master_open().map_err(Error::OpenPty)?;
+
+    if slave_name.is_some() {
+        *slave_name = Some(slname.clone());
+    }
+
+    // Fork process
+    match fork() {
+        Ok(ForkResult::Parent { child }) => {
+            *master_fd = Some(mfd.into_raw_fd());
+            Ok(ForkResult::Parent { child })
+        }
+        Ok(ForkResult::Child) => {
+            // The caller must not carry on as a second recorder if the child can't be set up.
+            if let Err(e) = attach_slave(mfd, &slname, slave_termios, slave_win_size) {
+                eprintln!("script-rs: can not set up {}: {}", slname, e);
+                unsafe { libc::_exit(1) };
+            }
+            Ok(ForkResult::Child)
+        }
+        Err(err) => {
+            let _ = close(mfd.into_raw_fd());
+            Err(Error::Fork(err))
+        }
+    }
+}
+
+/// Makes the pty slave the controlling terminal and stdio of the forked child.
+fn attach_slave(mfd: PtyMaster, slname: &str, slave_termios: Option<&Termios>, slave_win_size: winsize) -> Result<()> {
+    // Set session id to child process
+    setsid()?;
+    close(mfd.into_raw_fd())?;
+
+    let slave_fd = open(Path::new(slname), OFlag::O_RDWR, Mode::empty())?;
+
+    // For BSD
+    if cfg!(target_os = "openbsd") {
+        unsafe { ioctl::tiocsctty(0, &slave_fd) }?;
+    }
+
+    if let Some(slave_termios) = slave_termios {
+        tcsetattr(slave_fd, SetArg::TCSANOW, slave_termios)?;
+    }
+    unsafe { ioctl::tiocswinsz(slave_fd, &slave_win_size) }?;
+
+    dup2(slave_fd, STDIN_FILENO)?;
+    dup2(slave_fd, STDOUT_FILENO)?;
+    dup2(slave_fd, STDERR_FILENO)?;
+
+    if slave_fd > STDERR_FILENO {
+        close(slave_fd)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) mod ioctl {
+    use nix::libc::{winsize, TIOCGWINSZ, TIOCSWINSZ, TIOCSCTTY};
+    use nix::*;
+    ioctl_write_ptr_bad!(tiocswinsz, TIOCSWINSZ, winsize);
+    ioctl_read_bad!(tiocgwinsz, TIOCGWINSZ, winsize);
+    ioctl_write_ptr_bad!(tiocsctty, TIOCSCTTY, i32);
+}
+
@@ -0,0 +1,149 @@
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+/// Masks parts of a recording as it is played back, leaving the recording itself untouched.
+///
+/// A rules file has one rule per line; empty lines and lines starting with `#` are ignored:
+///
+/// ```text
+/// text <string>       mask every occurrence of the string
+/// time <start>-<end>  mask all text printed between these times, in seconds
+/// ```
+pub struct Redactor {
+    texts: Vec<Vec<u8>>,
+    times: Vec<Range<Duration>>,
+    pending: Vec<u8>,
+    escape: Escape,
+}
+
+impl Redactor {
+    pub fn load(path: &Path) -> io::Result<Redactor> {
+        let mut redactor = Redactor {
+            texts: Vec::new(),
+            times: Vec::new(),
+            pending: Vec::new(),
+            escape: Escape::None,
+        };
+
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: invalid rule: {}", path.display(), number + 1, line),
+                )
+            };
+            let (kind, value) = line.split_once(' ').ok_or_else(invalid)?;
+            match kind {
+                "text" if !value.is_empty() => redactor.texts.push(value.as_bytes().to_vec()),
+                "time" => redactor.times.push(parse_range(value.trim()).ok_or_else(invalid)?),
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(redactor)
+    }
+
+    /// Redacts a chunk printed at `at` into the recording. The tail of a chunk that could be
+    /// the start of a masked string is held back until the next chunk shows whether it is.
+    pub fn apply(&mut self, chunk: &[u8], at: Duration) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        self.mask_texts();
+
+        let keep = self.partial_match();
+        let mut out = self.pending.split_off(0);
+        self.pending = out.split_off(out.len() - keep);
+
+        if self.times.iter().any(|range| range.contains(&at)) {
+            self.mask_printable(&mut out);
+        }
+        out
+    }
+
+    /// Returns what is still held back at the end of the recording.
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+
+    fn mask_texts(&mut self) {
+        for text in &self.texts {
+            let mut i = 0;
+            while i + text.len() <= self.pending.len() {
+                if self.pending[i..].starts_with(text) {
+                    for byte in &mut self.pending[i..i + text.len()] {
+                        *byte = b'*';
+                    }
+                    i += text.len();
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Length of the longest suffix of the pending output that is a prefix of a masked string.
+    fn partial_match(&self) -> usize {
+        self.texts
+            .iter()
+            .flat_map(|text| (1..text.len()).map(move |len| &text[..len]))
+            .filter(|prefix| self.pending.ends_with(prefix))
+            .map(|prefix| prefix.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Replaces each printed character with `*`, leaving control characters and escape
+    /// sequences alone so that the screen layout survives.
+    fn mask_printable(&mut self, out: &mut Vec<u8>) {
+        let mut masked = Vec::with_capacity(out.len());
+        for &byte in out.iter() {
+            match self.escape {
+                Escape::None => match byte {
+                    0x1b => {
+                        self.escape = Escape::Start;
+                        masked.push(byte);
+                    }
+                    0x21..=0x7e | 0xc0..=0xff => masked.push(b'*'),
+                    // UTF-8 continuation bytes belong to a character already masked.
+                    0x80..=0xbf => {}
+                    _ => masked.push(byte),
+                },
+                Escape::Start => {
+                    self.escape = if byte == b'[' { Escape::Csi } else { Escape::None };
+                    masked.push(byte);
+                }
+                Escape::Csi => {
+                    if (0x40..=0x7e).contains(&byte) {
+                        self.escape = Escape::None;
+                    }
+                    masked.push(byte);
+                }
+            }
+        }
+        *out = masked;
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Escape {
+    None,
+    Start,
+    Csi,
+}
+
+fn parse_range(s: &str) -> Option<Range<Duration>> {
+    let (start, end) = s.split_once('-')?;
+    let start = start.trim().parse::<f64>().ok()?;
+    let end = end.trim().parse::<f64>().ok()?;
+    if start < 0.0 || !start.is_finite() || !end.is_finite() || end < start {
+        return None;
+    }
+    Some(Duration::from_secs_f64(start)..Duration::from_secs_f64(end))
+}
@@ -0,0 +1,169 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::libc::STDIN_FILENO;
+use nix::sys::select::{select, FdSet};
+use nix::sys::time::{TimeVal, TimeValLike};
+use nix::unistd::read;
+
+use crate::redact::Redactor;
+
+/// Plays a typescript back with the delays from its timing file, divided by `speed`.
+///
+/// When `interactive` is set, keys read from stdin control the playback: space pauses and
+/// resumes, `.` outputs the next chunk while paused, and `q` or Ctrl-C quits. A `redactor`
+/// masks the output before it is shown.
+pub fn replay(
+    typescript: &Path,
+    timing: &Path,
+    speed: f64,
+    interactive: bool,
+    mut redactor: Option<Redactor>,
+) -> io::Result<()> {
+    let timing = BufReader::new(File::open(timing)?);
+    let mut script = BufReader::new(File::open(typescript)?);
+    skip_header(&mut script)?;
+
+    let mut player = Player {
+        speed,
+        paused: false,
+        interactive,
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut skipped = Duration::from_secs(0);
+    let mut at = Duration::from_secs(0);
+    for line in timing.lines() {
+        let line = line?;
+        let (stream, delay, len) = parse_timing(&line).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("invalid timing line: {}", line))
+        })?;
+
+        // Input is logged elsewhere and information entries print nothing, but their delays
+        // still count towards the next output.
+        if stream != 'O' {
+            skipped += delay;
+            continue;
+        }
+        let delay = delay + skipped;
+        skipped = Duration::from_secs(0);
+        at += delay;
+
+        if !player.wait(delay)? {
+            break;
+        }
+
+        let mut chunk = vec![0; len];
+        script.read_exact(&mut chunk)?;
+        if let Some(r) = redactor.as_mut() {
+            chunk = r.apply(&chunk, at);
+        }
+        out.write_all(&chunk)?;
+        out.flush()?;
+    }
+
+    if let Some(r) = redactor.as_mut() {
+        out.write_all(&r.finish())?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Skips the `Script started` line that util-linux script puts before the recorded output.
+fn skip_header<R: BufRead>(script: &mut R) -> io::Result<()> {
+    if script.fill_buf()?.starts_with(b"Script started") {
+        script.read_until(b'\n', &mut Vec::new())?;
+    }
+    Ok(())
+}
+
+/// Parses a classic `<delay> <bytes>` line, or an advanced one that starts with its stream:
+/// `O` for output, `I` for input, or `H` or `S` for information and signals, whose other
+/// fields are not lengths.
+fn parse_timing(line: &str) -> Option<(char, Duration, usize)> {
+    let mut fields = line.split_whitespace().peekable();
+    let stream = match *fields.peek()? {
+        "O" | "I" | "H" | "S" => fields.next()?.chars().next()?,
+        _ => 'O',
+    };
+    let delay = fields.next()?.parse::<f64>().ok()?;
+    let len = match stream {
+        'O' | 'I' => fields.next()?.parse().ok()?,
+        _ => 0,
+    };
+    if delay < 0.0 || !delay.is_finite() {
+        return None;
+    }
+    Some((stream, Duration::from_secs_f64(delay), len))
+}
+
+struct Player {
+    speed: f64,
+    paused: bool,
+    interactive: bool,
+}
+
+impl Player {
+    /// Waits until the next chunk is due. Returns false when the user quit.
+    fn wait(&mut self, delay: Duration) -> io::Result<bool> {
+        let mut remaining = delay.div_f64(self.speed);
+        let mut deadline = Instant::now() + remaining;
+
+        loop {
+            let now = Instant::now();
+            if !self.paused && now >= deadline {
+                return Ok(true);
+            }
+
+            if !self.interactive {
+                thread::sleep(deadline - now);
+                continue;
+            }
+
+            let timeout = if self.paused { None } else { Some(deadline - now) };
+            let key = match read_key(timeout)? {
+                Some(key) => key,
+                None => continue,
+            };
+
+            match key {
+                b' ' => {
+                    if self.paused {
+                        deadline = Instant::now() + remaining;
+                    } else {
+                        remaining = deadline.saturating_duration_since(Instant::now());
+                    }
+                    self.paused = !self.paused;
+                }
+                b'.' if self.paused => return Ok(true),
+                b'q' | 0x03 => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Reads a key from stdin, or returns None once `timeout` passes.
+fn read_key(timeout: Option<Duration>) -> io::Result<Option<u8>> {
+    let mut fds = FdSet::new();
+    fds.insert(STDIN_FILENO);
+
+    let mut timeout = timeout.map(|t| TimeVal::microseconds(t.as_micros() as i64));
+    match select(Some(STDIN_FILENO + 1), Some(&mut fds), None, None, timeout.as_mut()) {
+        Ok(0) => return Ok(None),
+        Ok(_) => {}
+        Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => return Ok(None),
+        Err(e) => return Err(io::Error::other(e)),
+    }
+
+    let mut key = [0; 1];
+    match read(STDIN_FILENO, &mut key) {
+        Ok(0) => Ok(Some(b'q')),
+        Ok(_) => Ok(Some(key[0])),
+        Err(e) => Err(io::Error::other(e)),
+    }
+}
@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+/// Lossy sampling for extreme output rates: admits up to `rate` bytes of output per second
+/// and counts the bytes it drops beyond that.
+pub struct Sampler {
+    rate: u64,
+    window: Instant,
+    used: u64,
+    dropped: u64,
+}
+
+impl Sampler {
+    pub fn new(rate: u64) -> Sampler {
+        Sampler {
+            rate,
+            window: Instant::now(),
+            used: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Returns whether a chunk of `len` bytes arriving at `now` is recorded. The first chunk of
+    /// every second is, however large, so that a busy session still leaves samples behind.
+    pub fn admit(&mut self, len: usize, now: Instant) -> bool {
+        if now.saturating_duration_since(self.window) >= Duration::from_secs(1) {
+            self.window = now;
+            self.used = 0;
+        }
+
+        let len = len as u64;
+        if self.used > 0 && self.used + len > self.rate {
+            self.dropped += len;
+            return false;
+        }
+        self.used += len;
+        true
+    }
+
+    /// Returns the number of bytes dropped since the last call.
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::replace(&mut self.dropped, 0)
+    }
+}
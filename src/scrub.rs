@@ -0,0 +1,88 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Removes the input entries from an advanced timing file, in place, so that what was typed in
+/// the session doesn't go along when the recording is shared. Their delays are added to the
+/// entry after them, which keeps the rest of the recording replaying at the same pace. Returns
+/// how many entries were removed.
+pub fn scrub_input(timing: &Path) -> io::Result<usize> {
+    let (scrubbed, removed) = scrub(&fs::read_to_string(timing)?)?;
+    if removed > 0 {
+        // Renamed over the original, so that an interrupted scrub leaves it whole.
+        let mut temp = timing.as_os_str().to_owned();
+        temp.push(".scrub");
+        fs::write(&temp, scrubbed)?;
+        fs::rename(&temp, timing)?;
+    }
+    Ok(removed)
+}
+
+fn scrub(data: &str) -> io::Result<(String, usize)> {
+    let mut scrubbed = String::with_capacity(data.len());
+    let mut removed = 0;
+    // Delay of the removed entries since the last one kept, in microseconds.
+    let mut carried = 0;
+    for line in data.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (stream, delay, rest) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(stream @ ("O" | "I" | "H" | "S")), Some(delay), rest) => (stream, delay, rest),
+            // Classic timing files have no input to remove.
+            _ => {
+                scrubbed.push_str(line);
+                scrubbed.push('\n');
+                continue;
+            }
+        };
+        let delay = match delay.parse::<f64>() {
+            Ok(delay) if delay >= 0.0 && delay.is_finite() => (delay * 1e6).round() as u64 + carried,
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid timing line: {}", line)));
+            }
+        };
+        if stream == "I" {
+            carried = delay;
+            removed += 1;
+            continue;
+        }
+        carried = 0;
+        scrubbed.push_str(&format!("{} {}.{:06}", stream, delay / 1_000_000, delay % 1_000_000));
+        if let Some(rest) = rest {
+            scrubbed.push(' ');
+            scrubbed.push_str(rest);
+        }
+        scrubbed.push('\n');
+    }
+    Ok((scrubbed, removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_input_delays_into_the_next_entry() {
+        let data = "O 0.100000 5\nI 0.250000 1\nI 0.050000 1\nO 0.010000 3\nH 0.000000 MARKER 1\n";
+        let (scrubbed, removed) = scrub(data).unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(scrubbed, "O 0.100000 5\nO 0.310000 3\nH 0.000000 MARKER 1\n");
+    }
+
+    #[test]
+    fn keeps_information_values_as_they_are() {
+        let data = "I 1.500000 4\nH 0.000001 WATCH a  b\\nc\n";
+        let (scrubbed, _) = scrub(data).unwrap();
+        assert_eq!(scrubbed, "H 1.500001 WATCH a  b\\nc\n");
+    }
+
+    #[test]
+    fn leaves_classic_timing_files_alone() {
+        let data = "0.100000 5\n1.000000 12\n";
+        assert_eq!(scrub(data).unwrap(), (data.to_owned(), 0));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!(scrub("I soon 4\n").is_err());
+    }
+}
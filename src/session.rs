@@ -0,0 +1,1685 @@
+use std::collections::VecDeque;
+use std::ffi::{CString, NulError, OsString};
+use std::fs;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::os::unix::prelude::*;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nix::errno::Errno;
+use nix::fcntl::{open, OFlag};
+use nix::libc::{self, winsize, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use nix::sys::select::{select, FdSet};
+use nix::sys::signal::{kill, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::stat::Mode;
+use nix::sys::termios::tcgetattr;
+use nix::sys::time::{TimeVal, TimeValLike};
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::*;
+
+use crate::error::{Error, Result};
+
+use crate::broadcast::Broadcast;
+use crate::clock;
+use crate::format::{Encoder, Format, SessionInfo};
+use crate::fswatch::FsWatch;
+use crate::gzip::GzipWriter;
+use crate::mirror::TextMirror;
+use crate::policy::{CommandLines, DenyAction, DenyList};
+use crate::prefix::{Command, PrefixFilter, PrefixKey};
+use crate::proctree;
+use crate::sampler::Sampler;
+use crate::pty::{ioctl, pty_fork};
+use crate::siem::{SiemFormat, SiemLog};
+use crate::stamp::LineStamper;
+use crate::systemd::Notifier;
+use crate::timing::TimingWriter;
+use crate::tty::{self, RestoreTerminal, TITLE_STATUS};
+use crate::usage::{wait4, Usage};
+
+/// Policy applied when writing to the output file fails with ENOSPC or EDQUOT.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WriteErrorPolicy {
+    /// Stop recording for good, but keep relaying the session.
+    Abort,
+    /// Pause recording as the pause command does, so that it can be resumed once there is
+    /// space again.
+    PauseRecording,
+    /// Drop the output that could not be written and retry with the next chunk. Where the
+    /// recording picks up again, a `DROPPED` information entry notes how much was lost.
+    Drop,
+}
+
+impl FromStr for WriteErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(WriteErrorPolicy::Abort),
+            "pause-recording" => Ok(WriteErrorPolicy::PauseRecording),
+            "drop" => Ok(WriteErrorPolicy::Drop),
+            _ => Err(format!("unknown write error policy: {}", s)),
+        }
+    }
+}
+
+static HANGUP: AtomicBool = AtomicBool::new(false);
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+static INTERRUPT: AtomicBool = AtomicBool::new(false);
+static RESIZED: AtomicBool = AtomicBool::new(false);
+static CHILD_EXITED: AtomicBool = AtomicBool::new(false);
+
+/// How the output file is compressed.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Compression {
+    /// gzip, readable with zcat up to the last flush even if the recorder is killed.
+    Gzip,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Err("zstd compression is not supported, use gzip".to_owned()),
+            _ => Err(format!("unknown compression: {}", s)),
+        }
+    }
+}
+
+/// A recorded terminal session: a shell, or a command run by it, on a new pty whose output is
+/// relayed to the terminal on stdin/stdout and written to the output file.
+///
+/// ```no_run
+/// let status = script_rs::Session::new().command("make").output("build.log").spawn()?;
+/// # Ok::<(), script_rs::Error>(())
+/// ```
+pub struct Session {
+    output: PathBuf,
+    append: bool,
+    command: Option<String>,
+    quiet: bool,
+    format: Format,
+    timing: Option<PathBuf>,
+    log_in: Option<PathBuf>,
+    log_io: Option<PathBuf>,
+    input_logging_acknowledged: bool,
+    on_write_error: WriteErrorPolicy,
+    prefix_key: PrefixKey,
+    mirror_text: Option<PathBuf>,
+    linger: Option<Duration>,
+    title_status: bool,
+    siem: Option<(PathBuf, SiemFormat)>,
+    utc: bool,
+    anchor_interval: Option<Duration>,
+    flush: bool,
+    low_overhead: bool,
+    max_rate: Option<u64>,
+    snapshot_processes: bool,
+    max_size: Option<u64>,
+    kill_at_max_size: bool,
+    watch: Vec<PathBuf>,
+    rotate_size: Option<u64>,
+    rotate_keep: Option<usize>,
+    deny: Option<(PathBuf, DenyAction)>,
+    compress: Option<Compression>,
+    broadcast: Option<PathBuf>,
+    timestamp: Option<String>,
+    env: Vec<(String, String)>,
+    env_remove: Vec<String>,
+    env_clear: bool,
+    login: bool,
+    args: Vec<String>,
+    shell: Option<String>,
+    force: bool,
+    freeze_size: bool,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session::new()
+    }
+}
+
+impl Session {
+    /// A session running `$SHELL` interactively and recording to `typescript`.
+    pub fn new() -> Session {
+        Session {
+            output: PathBuf::from("typescript"),
+            append: false,
+            command: None,
+            quiet: false,
+            format: Format::Script,
+            timing: None,
+            log_in: None,
+            log_io: None,
+            input_logging_acknowledged: false,
+            on_write_error: WriteErrorPolicy::Abort,
+            prefix_key: PrefixKey::default(),
+            mirror_text: None,
+            linger: None,
+            title_status: false,
+            siem: None,
+            utc: false,
+            anchor_interval: None,
+            flush: false,
+            low_overhead: false,
+            max_rate: None,
+            snapshot_processes: false,
+            max_size: None,
+            kill_at_max_size: false,
+            watch: Vec::new(),
+            rotate_size: None,
+            rotate_keep: None,
+            deny: None,
+            compress: None,
+            broadcast: None,
+            timestamp: None,
+            env: Vec::new(),
+            env_remove: Vec::new(),
+            env_clear: false,
+            login: false,
+            args: Vec::new(),
+            shell: None,
+            force: false,
+            freeze_size: false,
+        }
+    }
+
+    /// Records to this file.
+    pub fn output<P: Into<PathBuf>>(mut self, path: P) -> Session {
+        self.output = path.into();
+        self
+    }
+
+    /// Appends to the output files instead of overwriting them.
+    pub fn append(mut self, append: bool) -> Session {
+        self.append = append;
+        self
+    }
+
+    /// Runs this command with `$SHELL -c` instead of an interactive shell.
+    pub fn command<S: Into<String>>(mut self, command: S) -> Session {
+        self.command = Some(command.into());
+        self
+    }
+
+    /// Runs this program instead of `$SHELL`.
+    pub fn shell<S: Into<String>>(mut self, shell: S) -> Session {
+        self.shell = Some(shell.into());
+        self
+    }
+
+    /// Records even when running inside another recording, as told by `$SCRIPT`.
+    pub fn force(mut self, force: bool) -> Session {
+        self.force = force;
+        self
+    }
+
+    /// Keeps the size the terminal had at the start for the whole session, ignoring resizes,
+    /// so that the recording has one geometry throughout.
+    pub fn freeze_size(mut self, freeze: bool) -> Session {
+        self.freeze_size = freeze;
+        self
+    }
+
+    /// Starts the shell as a login shell, with `-` in front of the name in its `argv[0]`.
+    pub fn login(mut self, login: bool) -> Session {
+        self.login = login;
+        self
+    }
+
+    /// Passes an extra argument to the shell, after `-c <command>` if there is one.
+    pub fn arg<S: Into<String>>(mut self, arg: S) -> Session {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Leaves out the start and done lines.
+    pub fn quiet(mut self, quiet: bool) -> Session {
+        self.quiet = quiet;
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> Session {
+        self.format = format;
+        self
+    }
+
+    /// Writes scriptreplay timing data to this file. It is in util-linux's advanced format when
+    /// an option records more than output into it, such as input logging or anchors; markers
+    /// set with the prefix key are only kept in that format.
+    pub fn timing<P: Into<PathBuf>>(mut self, path: P) -> Session {
+        self.timing = Some(path.into());
+        self
+    }
+
+    /// Also logs the input to this file. That includes passwords typed at prompts, which a
+    /// warning on stderr points out at the start. Needs `input_logging_acknowledged`.
+    pub fn log_in<P: Into<PathBuf>>(mut self, path: P) -> Session {
+        self.log_in = Some(path.into());
+        self
+    }
+
+    /// Logs both input and output to this file, with the same warning and opt-in as `log_in`.
+    pub fn log_io<P: Into<PathBuf>>(mut self, path: P) -> Session {
+        self.log_io = Some(path.into());
+        self
+    }
+
+    /// Confirms that whoever starts the session knows that input logs hold everything typed,
+    /// passwords included. Sessions with `log_in` or `log_io` don't start without it.
+    pub fn input_logging_acknowledged(mut self, acknowledged: bool) -> Session {
+        self.input_logging_acknowledged = acknowledged;
+        self
+    }
+
+    pub fn on_write_error(mut self, policy: WriteErrorPolicy) -> Session {
+        self.on_write_error = policy;
+        self
+    }
+
+    /// Key that introduces recorder commands typed on stdin.
+    pub fn prefix_key(mut self, key: PrefixKey) -> Session {
+        self.prefix_key = key;
+        self
+    }
+
+    /// Keeps an up-to-date plain text copy of the session in this file.
+    pub fn mirror_text<P: Into<PathBuf>>(mut self, path: P) -> Session {
+        self.mirror_text = Some(path.into());
+        self
+    }
+
+    /// Keeps recording for this long after the terminal hangs up.
+    pub fn linger(mut self, linger: Duration) -> Session {
+        self.linger = Some(linger);
+        self
+    }
+
+    /// Shows the elapsed time and output size in the terminal title.
+    pub fn title_status(mut self, title_status: bool) -> Session {
+        self.title_status = title_status;
+        self
+    }
+
+    /// Logs session start and end events to this file, or to syslog if it is `syslog`.
+    pub fn siem<P: Into<PathBuf>>(mut self, target: P, format: SiemFormat) -> Session {
+        self.siem = Some((target.into(), format));
+        self
+    }
+
+    /// Writes dates in UTC instead of local time.
+    pub fn utc(mut self, utc: bool) -> Session {
+        self.utc = utc;
+        self
+    }
+
+    /// Embeds the wall clock time into the recording this often, so that it can be lined up
+    /// with other logs despite clock adjustments or suspends during the session. This needs
+    /// the asciicast format or a timing file, which is then written in the advanced format.
+    pub fn anchor_interval(mut self, interval: Duration) -> Session {
+        self.anchor_interval = Some(interval);
+        self
+    }
+
+    /// Flushes the output file after every chunk, for following it live. Otherwise output is
+    /// buffered and written in larger blocks.
+    pub fn flush(mut self, flush: bool) -> Session {
+        self.flush = flush;
+        self
+    }
+
+    /// Keeps the cost of recording down for always-on deployments: only output is recorded,
+    /// through a large buffer that is only flushed when full. Timing, input logs, the text
+    /// mirror, anchors and flushing can't be used in this mode.
+    pub fn low_overhead(mut self, low_overhead: bool) -> Session {
+        self.low_overhead = low_overhead;
+        self
+    }
+
+    /// Records at most this many bytes of output per second, dropping the rest. Where the
+    /// recording picks up again, a `DROPPED` information entry notes how much was lost; in
+    /// formats with no room for it, the total is reported on stderr at the end instead.
+    pub fn max_rate(mut self, bytes_per_second: u64) -> Session {
+        self.max_rate = Some(bytes_per_second);
+        self
+    }
+
+    /// Records the child's process tree along with every marker, which needs the asciicast
+    /// format or a timing file.
+    pub fn snapshot_processes(mut self, snapshot: bool) -> Session {
+        self.snapshot_processes = snapshot;
+        self
+    }
+
+    /// Stops recording once this many bytes of output have been written to the output file.
+    /// The session itself goes on unless `kill_at_max_size` is set.
+    pub fn max_size(mut self, bytes: u64) -> Session {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Ends the session, stopping the child, when the output file reaches `max_size`.
+    pub fn kill_at_max_size(mut self, kill: bool) -> Session {
+        self.kill_at_max_size = kill;
+        self
+    }
+
+    /// Journals the files created, modified, deleted and moved below this directory as
+    /// `FILE` information entries, which needs the asciicast format or a timing file. Can be
+    /// given more than once; Linux only.
+    pub fn watch<P: Into<PathBuf>>(mut self, dir: P) -> Session {
+        self.watch.push(dir.into());
+        self
+    }
+
+    /// Rotates the output file once this many bytes of output have been written to it: the
+    /// file is moved aside to the next free `<output>.<n>` and recording goes on in a fresh
+    /// one, along with the timing file.
+    pub fn rotate_size(mut self, bytes: u64) -> Session {
+        self.rotate_size = Some(bytes);
+        self
+    }
+
+    /// Keeps only this many rotated files, removing the oldest.
+    pub fn rotate_keep(mut self, files: usize) -> Session {
+        self.rotate_keep = Some(files);
+        self
+    }
+
+    /// Checks each command line entered against the patterns in this file, and warns about,
+    /// annotates or refuses the ones that match.
+    pub fn deny<P: Into<PathBuf>>(mut self, patterns: P, action: DenyAction) -> Session {
+        self.deny = Some((patterns.into(), action));
+        self
+    }
+
+    /// Compresses the output file as it is written. Size limits still count the output
+    /// before compression.
+    pub fn compress(mut self, compression: Compression) -> Session {
+        self.compress = Some(compression);
+        self
+    }
+
+    /// Streams the output live to read-only viewers connecting to a Unix socket created at
+    /// this path. Nothing is sent while the recording is paused.
+    pub fn broadcast<P: Into<PathBuf>>(mut self, socket: P) -> Session {
+        self.broadcast = Some(socket.into());
+        self
+    }
+
+    /// Prefixes each line of output in the typescript with the wall clock time, formatted
+    /// with this strftime(3) format. Timing data counts the prefixes, so replays show them.
+    pub fn timestamp<S: Into<String>>(mut self, format: S) -> Session {
+        self.timestamp = Some(format.into());
+        self
+    }
+
+    /// Sets an environment variable for the shell.
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Session {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Keeps an environment variable, such as a secret, away from the shell.
+    pub fn env_remove<K: Into<String>>(mut self, key: K) -> Session {
+        self.env_remove.push(key.into());
+        self
+    }
+
+    /// Starts the shell with only the basic variables from `ENV_KEPT`, plus those set with
+    /// `env`.
+    pub fn env_clear(mut self, clear: bool) -> Session {
+        self.env_clear = clear;
+        self
+    }
+
+    /// Runs the session until the child exits or the recording is stopped, and returns the
+    /// child's exit status.
+    pub fn spawn(self) -> Result<i32> {
+        if self.format != Format::Script && self.timing.is_some() {
+            return Err(Error::Invalid("timing data is only written for the script format"));
+        }
+        if self.format == Format::Asciicast && self.append {
+            return Err(Error::Invalid("asciicast recordings can not be appended to"));
+        }
+
+        if self.anchor_interval == Some(Duration::from_secs(0)) {
+            return Err(Error::Invalid("the anchor interval must not be zero"));
+        }
+        if self.max_rate == Some(0) {
+            return Err(Error::Invalid("the maximum output rate must not be zero"));
+        }
+        if self.max_size == Some(0) {
+            return Err(Error::Invalid("the maximum output size must not be zero"));
+        }
+        if let Some(outer) = std::env::var_os("SCRIPT").filter(|_| !self.force) {
+            return Err(Error::Nested(outer.to_string_lossy().into_owned()));
+        }
+        if self.timestamp.is_some() && self.format != Format::Script {
+            return Err(Error::Invalid("timestamps can only be added in the script format"));
+        }
+        if self.rotate_size == Some(0) {
+            return Err(Error::Invalid("the rotation size must not be zero"));
+        }
+        if self.rotate_keep.is_some() && self.rotate_size.is_none() {
+            return Err(Error::Invalid("--rotate-keep needs a rotation size"));
+        }
+        if self.rotate_size.is_some() && self.max_size.is_some() {
+            return Err(Error::Invalid("the output can not be both rotated and limited in size"));
+        }
+        if self.kill_at_max_size && self.max_size.is_none() {
+            return Err(Error::Invalid("--kill-at-max-size needs a maximum output size"));
+        }
+        if (self.log_in.is_some() || self.log_io.is_some()) && !self.input_logging_acknowledged {
+            return Err(Error::Invalid(
+                "input logging records passwords too, --i-understand-input-logging-risks turns it on",
+            ));
+        }
+        if self.low_overhead
+            && (self.timing.is_some()
+                || self.log_in.is_some()
+                || self.log_io.is_some()
+                || self.mirror_text.is_some()
+                || self.anchor_interval.is_some()
+                || self.flush)
+        {
+            return Err(Error::Invalid("low overhead mode only records output"));
+        }
+        // Information entries only have room in asciicast recordings and advanced timing files.
+        let info_sink = self.format == Format::Asciicast || self.timing.is_some();
+        if self.anchor_interval.is_some() && !info_sink {
+            return Err(Error::Invalid("wall clock anchors need the asciicast format or a timing file"));
+        }
+        if !self.watch.is_empty() && !info_sink {
+            return Err(Error::Invalid("watching files needs the asciicast format or a timing file"));
+        }
+        if self.snapshot_processes && !info_sink {
+            return Err(Error::Invalid("process snapshots need the asciicast format or a timing file"));
+        }
+        // Denied commands are noted in the recording by both the annotate and kill actions.
+        let deny_notes = self.deny.as_ref().is_some_and(|(_, action)| *action != DenyAction::Warn);
+        if self.deny.as_ref().is_some_and(|(_, action)| *action == DenyAction::Annotate) && !info_sink {
+            return Err(Error::Invalid("annotating denied commands needs the asciicast format or a timing file"));
+        }
+
+        let shell = match self.shell {
+            Some(ref shell) => shell.clone(),
+            None => std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned()),
+        };
+        let nul = |_| Error::Invalid("the shell and command must not contain NUL bytes");
+        let shell_c = CString::new(shell.as_str()).map_err(nul)?;
+        // Checked here so that a bad shell is reported before anything is set up.
+        if unsafe { libc::access(shell_c.as_ptr(), libc::X_OK) } != 0 {
+            return Err(Error::Exec(shell, nix::Error::Sys(Errno::last())));
+        }
+        if Path::new(&shell).is_dir() {
+            return Err(Error::Exec(shell, nix::Error::Sys(Errno::EISDIR)));
+        }
+        // A login shell is told so by a `-` in front of its name, as login(1) does it.
+        let arg0 = if self.login {
+            let name = Path::new(&shell).file_name().map_or(shell.as_str().into(), |name| name.to_string_lossy());
+            format!("-{}", name)
+        } else {
+            shell.clone()
+        };
+        let mut args = vec![CString::new(arg0).map_err(nul)?];
+        if let Some(ref command) = self.command {
+            args.push(CString::new("-c").unwrap());
+            args.push(CString::new(command.as_str()).map_err(nul)?);
+        }
+        for arg in &self.args {
+            args.push(CString::new(arg.as_str()).map_err(nul)?);
+        }
+
+        // Without a terminal on stdin, e.g. when input is piped in, the pty keeps its default
+        // settings and an 80x24 size, and nothing is switched to raw mode.
+        let termios = match tcgetattr(STDIN_FILENO).map_err(Error::terminal) {
+            Ok(termios) => Some(termios),
+            Err(Error::NotATty) => None,
+            Err(e) => return Err(e),
+        };
+        let interactive = termios.is_some();
+        let mut ws = winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        if interactive {
+            unsafe { ioctl::tiocgwinsz(STDIN_FILENO, &mut ws) }.map_err(Error::terminal)?;
+        }
+
+        // Open everything before forking, so that a failure doesn't leave a child behind.
+        let out_path = self.output.clone();
+        let script_file = open_output(&out_path, self.append).map_err(|e| open_error(&out_path, e))?;
+        let mut script = output_writer(script_file, self.buffer_capacity(), self.compress);
+        let mut out_index = 0;
+        let log_input = self.log_in.is_some() || self.log_io.is_some();
+        let advanced = log_input
+            || self.anchor_interval.is_some()
+            || !self.watch.is_empty()
+            || self.snapshot_processes
+            || self.max_rate.is_some()
+            || deny_notes;
+        // Markers don't switch the timing file to the advanced format by themselves, so that
+        // a plain -t still writes the classic one.
+        let marker_sink = self.format == Format::Asciicast || (self.timing.is_some() && advanced);
+        let mut timing = match self.timing {
+            Some(ref path) => Some(
+                TimingWriter::create(path, self.append, Duration::from_secs(0), advanced)
+                    .map_err(|e| Error::Open(path.clone(), e))?,
+            ),
+            None => None,
+        };
+        let mut fswatch = if self.watch.is_empty() {
+            None
+        } else {
+            let mut w = FsWatch::new().map_err(Error::Io)?;
+            for dir in &self.watch {
+                w.add(dir).map_err(|e| Error::Open(dir.clone(), e))?;
+            }
+            Some(w)
+        };
+        let mut in_log = match self.log_in {
+            Some(ref path) => Some(open_output(path, self.append).map_err(|e| open_error(path, e))?),
+            None => None,
+        };
+        let mut io_log = match self.log_io {
+            Some(ref path) => Some(open_output(path, self.append).map_err(|e| open_error(path, e))?),
+            None => None,
+        };
+        let mut mirror = match self.mirror_text {
+            Some(ref path) => Some(TextMirror::create(path).map_err(|e| Error::Open(path.clone(), e))?),
+            None => None,
+        };
+        let mut siem = match self.siem {
+            Some((ref target, format)) => {
+                Some(SiemLog::open(target, format, self.utc).map_err(|e| Error::Open(target.clone(), e))?)
+            }
+            None => None,
+        };
+
+        let mut broadcast = match self.broadcast {
+            Some(ref path) => Some(Broadcast::bind(path).map_err(|e| Error::Open(path.clone(), e))?),
+            None => None,
+        };
+        let deny_list = match self.deny {
+            Some((ref path, _)) => Some(DenyList::load(path).map_err(|e| Error::Open(path.clone(), e))?),
+            None => None,
+        };
+        let mut command_lines = CommandLines::new();
+        let mut stamper = match self.timestamp {
+            Some(ref format) => {
+                let format = CString::new(format.as_str())
+                    .map_err(|_| Error::Invalid("the timestamp format must not contain NUL bytes"))?;
+                Some(LineStamper::new(format, self.utc))
+            }
+            None => None,
+        };
+
+        // Must happen before forking so that the shell doesn't see $NOTIFY_SOCKET.
+        let notifier = Notifier::from_env();
+
+        let mut master_fd = None;
+        // Built before forking, like everything else the child needs.
+        let env = self.child_env().map_err(|_| Error::Invalid("environment variables must not contain NUL bytes"))?;
+
+        // The slave's name is only needed to send EOF at the end of piped input.
+        let mut slave_name = if interactive { None } else { Some(String::new()) };
+
+        let child = match pty_fork(&mut master_fd, &mut slave_name, termios.as_ref(), ws)? {
+            ForkResult::Parent { child } => child,
+            ForkResult::Child => {
+                let Err(e) = execve(&shell_c, &args, &env);
+                eprintln!("script-rs: {}", Error::Exec(shell, e));
+                unsafe { libc::_exit(127) };
+            }
+        };
+
+        // Closed when the session returns, however it ends.
+        let master = unsafe { File::from_raw_fd(master_fd.expect("pty_fork sets the master fd in the parent")) };
+        let master_fd = master.as_raw_fd();
+
+        let started = Instant::now();
+        let mut encoder = self.format.encoder(self.quiet, self.utc);
+
+        if !self.quiet {
+            println!("Script started, output log file is '{}'.", out_path.display());
+        }
+        if log_input {
+            // Shown even with quiet: whoever types in the session should know.
+            let banner = "input logging is on, everything typed is recorded, including passwords";
+            if isatty(STDERR_FILENO).unwrap_or(false) {
+                eprintln!("\x1b[1;31mscript-rs: {}\x1b[0m", banner);
+            } else {
+                eprintln!("script-rs: {}", banner);
+            }
+        }
+        let info = SessionInfo {
+            command: self.command.as_deref(),
+            columns: ws.ws_col,
+            lines: ws.ws_row,
+        };
+        let _ = script.write_all(&encoder.header(&info, started.elapsed()));
+
+        let siem_fields = session_fields(&out_path, child, self.command.as_deref());
+        if let Some(log) = siem.as_mut() {
+            if let Err(e) = log.emit("session-start", "Session started", 3, &siem_fields) {
+                warn(&format!("can not write SIEM event: {}", e));
+            }
+        }
+
+        // The previous handlers and the terminal settings are put back when these go out of
+        // scope, whichever way the session ends.
+        let terminal = RestoreTerminal;
+        let setup = install_handlers().and_then(|saved| {
+            if interactive {
+                tty::enter_raw_mode()?;
+            }
+            Ok(saved)
+        });
+        let _handlers = match setup {
+            Ok(saved) => saved,
+            Err(e) => {
+                stop_child(child, STOP_TIMEOUT);
+                return Err(e);
+            }
+        };
+
+        let mut recording = true;
+        let mut paused = false;
+        let mut dropping = false;
+        // Output thrown away while dropping on write errors.
+        let mut lost: u64 = 0;
+        // Set once the write error policy has dealt with a full output file.
+        let mut write_failed = false;
+        // Piped input is data, so it isn't scanned for recorder commands.
+        let mut prefix = PrefixFilter::new(if interactive { self.prefix_key } else { PrefixKey::none() });
+        let mut stdin_eof = false;
+        let mut sampler = self.max_rate.map(Sampler::new);
+        // Output dropped over the rate limit, reported at the end when the recording can't say.
+        let mut rate_dropped: u64 = 0;
+        let mut markers = 0;
+        // Output written to the current output file, checked against the size limit.
+        let mut recorded: u64 = 0;
+        let mut rotate_size = self.rotate_size;
+        let mut rotated = VecDeque::new();
+        // Rotated files are numbered on from the last one, so that pruning doesn't free up
+        // older numbers for newer files.
+        let mut rotate_index = 0;
+
+        // Set once the user's terminal is gone; the child is then hung up when it expires.
+        let mut linger_deadline: Option<Instant> = None;
+        // Set when SIGTERM was passed on to the child, which is hung up if it outlives it.
+        let mut stop_deadline: Option<Instant> = None;
+        let mut hangup = false;
+
+        let mut next_title = None;
+        if self.title_status {
+            // Save the current title on the terminal's title stack, reset_tty restores it.
+            let _ = write(STDOUT_FILENO, b"\x1b[22;2t");
+            TITLE_STATUS.store(true, Ordering::SeqCst);
+            next_title = Some(started);
+        }
+
+        let mut next_anchor = self.anchor_interval.map(|_| started);
+        let mut last_suspended = clock::suspended();
+
+        let mut next_ping = None;
+        if let Some(n) = notifier.as_ref() {
+            n.notify(&format!("READY=1\nSTATUS=Recording to {}", out_path.display()));
+            next_ping = n.watchdog_interval().map(|_| started);
+        }
+
+        // Set once the child is gone. The output left in the pty is drained before finishing.
+        let mut exit_status = None;
+        // Set when the session had to be ended because of an error.
+        let mut error = None;
+        let mut buf = vec![0; RELAY_BUFFER];
+
+        'relay: loop {
+            if CHILD_EXITED.swap(false, Ordering::SeqCst) && exit_status.is_none() {
+                if let Ok((status, usage)) = wait4(child, Some(WaitPidFlag::WNOHANG)) {
+                    exit_status = exit_code(status).map(|code| (code, usage));
+                }
+            }
+
+            if INTERRUPT.swap(false, Ordering::SeqCst) && exit_status.is_none() {
+                forward_signal(master_fd, child, Signal::SIGINT);
+            }
+
+            if TERMINATE.swap(false, Ordering::SeqCst) && exit_status.is_none() && stop_deadline.is_none() {
+                if let Some(n) = notifier.as_ref() {
+                    n.notify("STOPPING=1");
+                }
+                // The child gets to handle SIGTERM itself while its output is still recorded;
+                // it is only hung up if it is still there after the timeout.
+                forward_signal(master_fd, child, Signal::SIGTERM);
+                stop_deadline = Some(Instant::now() + STOP_TIMEOUT);
+            }
+
+            if RESIZED.swap(false, Ordering::SeqCst) && linger_deadline.is_none() && !self.freeze_size {
+                // Forward the new size to the child through the master side of the pty.
+                let (columns, lines) = (ws.ws_col, ws.ws_row);
+                if unsafe { ioctl::tiocgwinsz(STDIN_FILENO, &mut ws) }.is_ok() {
+                    let _ = unsafe { ioctl::tiocswinsz(master_fd, &ws) };
+                    if (ws.ws_col, ws.ws_row) != (columns, lines) && recording && !paused {
+                        let at = started.elapsed();
+                        let _ = script.write_all(&encoder.resize(at, ws.ws_col, ws.ws_row));
+                        if let Some(t) = timing.as_mut() {
+                            if let Err(e) = t.resize(at, ws.ws_col, ws.ws_row) {
+                                warn(&format!("can not write timing data: {}", e));
+                                timing = None;
+                            }
+                        }
+                    }
+                }
+            }
+
+            if linger_deadline.is_none() && (hangup || HANGUP.load(Ordering::SeqCst)) {
+                match self.linger {
+                    Some(linger) => linger_deadline = Some(Instant::now() + linger),
+                    None => {
+                        exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                        break;
+                    }
+                }
+            }
+
+            let now = Instant::now();
+            let mut wakeup = None;
+            if let Some(deadline) = linger_deadline {
+                if now >= deadline && exit_status.is_none() {
+                    exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                    break;
+                }
+                wakeup = Some(deadline);
+            } else if let Some(at) = next_title {
+                if now >= at {
+                    let size = script.get_ref().file().metadata().map(|m| m.len()).unwrap_or(0);
+                    update_title(started.elapsed(), size + script.buffer().len() as u64);
+                    next_title = Some(now + TITLE_INTERVAL);
+                }
+                wakeup = next_title;
+            }
+            // Session times come from the monotonic clock, which stops while the system is
+            // suspended, so a suspend leaves no delay behind. Mark it as a gap instead.
+            let suspended = clock::suspended();
+            if suspended >= last_suspended + SUSPEND_THRESHOLD {
+                if recording && !paused {
+                    let gap = clock::seconds(suspended - last_suspended);
+                    write_info(&mut script, &mut *encoder, &mut timing, started.elapsed(), "SUSPEND", &gap);
+                }
+                // The wall clock jumped, so re-anchor right away.
+                next_anchor = next_anchor.map(|_| now);
+            }
+            last_suspended = suspended;
+
+            if let (Some(interval), Some(at)) = (self.anchor_interval, next_anchor) {
+                if now >= at {
+                    if recording && !paused {
+                        let wall = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                        let wall = clock::seconds(wall);
+                        write_info(&mut script, &mut *encoder, &mut timing, started.elapsed(), "WALLCLOCK", &wall);
+                    }
+                    next_anchor = Some(now + interval);
+                }
+                wakeup = wakeup.into_iter().chain(next_anchor).min();
+            }
+            if let Some(deadline) = stop_deadline.filter(|_| exit_status.is_none()) {
+                if now >= deadline {
+                    exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                    break;
+                }
+                wakeup = wakeup.into_iter().chain(stop_deadline).min();
+            }
+            if let (Some(n), Some(at)) = (notifier.as_ref(), next_ping) {
+                if now >= at {
+                    n.notify("WATCHDOG=1");
+                    next_ping = n.watchdog_interval().map(|interval| now + interval);
+                }
+                wakeup = wakeup.into_iter().chain(next_ping).min();
+            }
+            let mut timeout =
+                wakeup.map(|at| TimeVal::milliseconds(at.saturating_duration_since(now).as_millis() as i64));
+            if exit_status.is_some() {
+                timeout = Some(TimeVal::zero());
+            }
+
+            let mut in_fds = FdSet::new();
+            if linger_deadline.is_none() && exit_status.is_none() && !stdin_eof {
+                in_fds.insert(STDIN_FILENO);
+            }
+            in_fds.insert(master_fd);
+            let extra_fds: Vec<RawFd> = fswatch
+                .as_ref()
+                .map(FsWatch::fd)
+                .into_iter()
+                .chain(broadcast.as_ref().map(Broadcast::fd))
+                .collect();
+            for &fd in &extra_fds {
+                in_fds.insert(fd);
+            }
+            let nfds = extra_fds.iter().fold(master_fd, |max, &fd| max.max(fd)) + 1;
+
+            match select(Some(nfds), Some(&mut in_fds), None, None, timeout.as_mut()) {
+                Ok(0) if exit_status.is_some() => break,
+                Ok(_) => {}
+                Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                Err(e) => {
+                    error = Some(Error::Sys(e));
+                    exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                    break;
+                }
+            }
+
+            if let Some(b) = broadcast.as_mut().filter(|b| in_fds.contains(b.fd())) {
+                b.accept();
+            }
+            if let Some(w) = fswatch.as_mut().filter(|w| in_fds.contains(w.fd())) {
+                for change in w.read() {
+                    if recording && !paused {
+                        write_info(&mut script, &mut *encoder, &mut timing, started.elapsed(), "FILE", &change);
+                    }
+                }
+            }
+
+            if in_fds.contains(STDIN_FILENO) {
+                // A hung up terminal reads as EOF or EIO; EAGAIN and EINTR are only transient.
+                let n = match read(STDIN_FILENO, &mut buf) {
+                    Ok(0) if !interactive => {
+                        stdin_eof = true;
+                        if let Some(ref name) = slave_name {
+                            send_eof(master_fd, name);
+                        }
+                        continue;
+                    }
+                    Ok(0) => {
+                        hangup = true;
+                        continue;
+                    }
+                    Ok(n) => n,
+                    Err(nix::Error::Sys(Errno::EAGAIN)) | Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                    Err(_) => {
+                        hangup = true;
+                        continue;
+                    }
+                };
+
+                let mut input = Vec::with_capacity(n);
+                for command in prefix.feed(&buf[..n], &mut input) {
+                    match command {
+                        Command::Pause => {
+                            paused = !paused;
+                            warn(if paused { "recording paused" } else { "recording resumed" });
+                        }
+                        Command::Switch => {
+                            let index = next_free_index(&out_path, self.timing.as_deref(), out_index);
+                            let path = indexed_path(&out_path, index);
+                            let timing_path = self.timing.as_ref().map(|base| indexed_path(base, index));
+                            let at = started.elapsed();
+                            match self.start_output(&path, timing_path.as_deref(), &mut *encoder, &ws, at, advanced) {
+                                Ok((new_script, new_timing)) => {
+                                    if let Err(e) = script.flush() {
+                                        warn(&format!("can not finish the output file: {}", e));
+                                    }
+                                    script = new_script;
+                                    timing = new_timing;
+                                    out_index = index;
+                                    recording = true;
+                                    dropping = false;
+                                    lost = 0;
+                                    write_failed = false;
+                                    recorded = 0;
+                                    warn(&format!("recording to {}", path.display()));
+                                }
+                                Err(e) => warn(&e.to_string()),
+                            }
+                        }
+                        Command::Stop => {
+                            exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                            warn("recording stopped");
+                            break 'relay;
+                        }
+                        Command::Mark if !marker_sink => warn("marker not recorded, the format has no room for it"),
+                        Command::Mark if recording && !paused => {
+                            markers += 1;
+                            let at = started.elapsed();
+                            let marker = markers.to_string();
+                            write_info(&mut script, &mut *encoder, &mut timing, at, "MARKER", &marker);
+                            if self.snapshot_processes {
+                                let processes = proctree::format(&proctree::snapshot(child));
+                                write_info(&mut script, &mut *encoder, &mut timing, at, "PROCESSES", &processes);
+                            }
+                            warn(&format!("marker {}", markers));
+                        }
+                        Command::Mark => warn("recording is paused, no marker set"),
+                    }
+                }
+                if let (Some(list), Some((_, action))) = (deny_list.as_ref(), self.deny.as_ref()) {
+                    let mut kill = false;
+                    for line in command_lines.feed(&input) {
+                        let pattern = match list.check(&line) {
+                            Some(pattern) => pattern,
+                            None => continue,
+                        };
+                        warn(&format!("denied command: {}", line));
+                        if *action != DenyAction::Warn && recording && !paused {
+                            write_info(&mut script, &mut *encoder, &mut timing, started.elapsed(), "DENIED", &line);
+                        }
+                        if let Some(log) = siem.as_mut() {
+                            let mut fields = siem_fields.clone();
+                            fields.push(("cs2Label", "deniedCommand".to_owned()));
+                            fields.push(("cs2", line.clone()));
+                            fields.push(("cs3Label", "pattern".to_owned()));
+                            fields.push(("cs3", pattern.to_owned()));
+                            if let Err(e) = log.emit("command-denied", "Denied command entered", 8, &fields) {
+                                warn(&format!("can not write SIEM event: {}", e));
+                            }
+                        }
+                        kill |= *action == DenyAction::Kill;
+                    }
+                    // The line is never handed to the shell.
+                    if kill {
+                        exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                        break;
+                    }
+                }
+                if !input.is_empty() {
+                    if let Err(e) = write_all(master_fd, &input) {
+                        error = Some(Error::Sys(e));
+                        exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                        break;
+                    }
+                }
+
+                if !input.is_empty() && recording && !paused {
+                    log_write(&mut in_log, &input, "input log");
+                    log_write(&mut io_log, &input, "input/output log");
+                    // Even input sizes and times give away typing, so they are only kept when
+                    // input is logged.
+                    if let Some(t) = timing.as_mut().filter(|_| log_input) {
+                        if let Err(e) = t.record_input(input.len(), started.elapsed()) {
+                            warn(&format!("can not write timing data: {}", e));
+                            timing = None;
+                        }
+                    }
+                }
+            }
+
+            if in_fds.contains(master_fd) {
+                let n = match read(master_fd, &mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(nix::Error::Sys(Errno::EAGAIN)) | Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                    Err(_) => break,
+                };
+                if linger_deadline.is_none() && write_all(STDOUT_FILENO, &buf[..n]).is_err() {
+                    hangup = true;
+                }
+
+                if let Some(b) = broadcast.as_mut().filter(|_| !paused) {
+                    b.send(&buf[..n]);
+                }
+
+                if !recording || paused {
+                    continue;
+                }
+
+                if let Some(s) = sampler.as_mut() {
+                    if !s.admit(n, Instant::now()) {
+                        continue;
+                    }
+                    let dropped = s.take_dropped();
+                    if dropped > 0 {
+                        rate_dropped += dropped;
+                        let dropped = dropped.to_string();
+                        write_info(&mut script, &mut *encoder, &mut timing, started.elapsed(), "DROPPED", &dropped);
+                    }
+                }
+
+                if let Some(m) = mirror.as_mut() {
+                    if let Err(e) = m.write(&buf[..n]) {
+                        warn(&format!("can not update text mirror: {}", e));
+                        mirror = None;
+                    }
+                }
+                log_write(&mut io_log, &buf[..n], "input/output log");
+
+                let stamped;
+                let output = match stamper.as_mut() {
+                    Some(s) => {
+                        stamped = s.apply(&buf[..n]);
+                        &stamped[..]
+                    }
+                    None => &buf[..n],
+                };
+
+                let at = started.elapsed();
+                // Output past the size limit is cut off; the part that still fits is recorded if
+                // it does so encoded, too.
+                let mut len = output.len();
+                let mut limit_reached = false;
+                if let Some(max) = self.max_size {
+                    let room = max.saturating_sub(recorded);
+                    if len as u64 >= room {
+                        len = room as usize;
+                        limit_reached = true;
+                    }
+                }
+                let mut data = encoder.output(&output[..len], at);
+                if limit_reached && recorded + data.len() as u64 > self.max_size.unwrap_or(0) {
+                    data.clear();
+                }
+                // While dropping, the disk only counts as having room again once the data is
+                // actually written, not just buffered.
+                let written = script
+                    .write_all(&data)
+                    .and_then(|_| if self.flush || dropping { script.flush() } else { Ok(()) });
+                match written {
+                    Ok(()) => {
+                        recorded += data.len() as u64;
+                        if dropping {
+                            dropping = false;
+                            write_failed = false;
+                            write_info(&mut script, &mut *encoder, &mut timing, at, "DROPPED", &lost.to_string());
+                            warn(&format!("recording resumed, {} bytes of output were lost", lost));
+                            lost = 0;
+                        }
+                        if let Some(t) = timing.as_mut().filter(|_| !data.is_empty()) {
+                            if let Err(e) = t.record(len, at) {
+                                warn(&format!("can not write timing data: {}", e));
+                                timing = None;
+                            }
+                        }
+                        if limit_reached {
+                            if self.kill_at_max_size {
+                                warn("output size limit reached, stopping the session");
+                                exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                                break;
+                            }
+                            recording = false;
+                            warn("output size limit reached, recording stopped");
+                        }
+                    }
+                    Err(e) => match out_of_space(&e) {
+                        Some(errno) => {
+                            write_failed = true;
+                            match self.on_write_error {
+                                WriteErrorPolicy::Abort => {
+                                    recording = false;
+                                    warn(&format!("{}, recording stopped", errno.desc()));
+                                }
+                                WriteErrorPolicy::PauseRecording => {
+                                    paused = true;
+                                    warn(&format!("{}, recording paused", errno.desc()));
+                                }
+                                WriteErrorPolicy::Drop => {
+                                    lost += (script.buffer().len() + data.len()) as u64;
+                                    script = discard_buffered(script);
+                                    if !dropping {
+                                        dropping = true;
+                                        warn(&format!("{}, dropping output", errno.desc()));
+                                    }
+                                }
+                            }
+                        }
+                        None => {
+                            error = Some(Error::Io(e));
+                            exit_status = Some(stop_child(child, STOP_TIMEOUT));
+                            break;
+                        }
+                    },
+                }
+
+                // Rotate between chunks, so that no chunk is split across files.
+                if rotate_size.is_some_and(|size| recorded >= size) {
+                    let _ = script.flush();
+                    let current = |base: &Path| match out_index {
+                        0 => base.to_path_buf(),
+                        index => indexed_path(base, index),
+                    };
+                    let path = current(&out_path);
+                    let timing_path = self.timing.as_deref().filter(|_| timing.is_some()).map(current);
+                    let index = next_free_index(&path, timing_path.as_deref(), rotate_index);
+                    let moved = indexed_path(&path, index);
+                    // The timing file is moved along with the output file, or given up on.
+                    let moved_timing = match fs::rename(&path, &moved) {
+                        Ok(()) => timing_path.as_ref().and_then(|timing_path| {
+                            let moved_timing = indexed_path(timing_path, index);
+                            match fs::rename(timing_path, &moved_timing) {
+                                Ok(()) => Some(moved_timing),
+                                Err(e) => {
+                                    warn(&format!("can not rotate {}: {}", timing_path.display(), e));
+                                    None
+                                }
+                            }
+                        }),
+                        Err(e) => {
+                            warn(&format!("can not rotate {}: {}, rotation stopped", path.display(), e));
+                            rotate_size = None;
+                            continue;
+                        }
+                    };
+                    let timing_path = timing_path.filter(|_| moved_timing.is_some());
+                    let at = started.elapsed();
+                    match self.start_output(&path, timing_path.as_deref(), &mut *encoder, &ws, at, advanced) {
+                        Ok((new_script, new_timing)) => {
+                            script = new_script;
+                            timing = new_timing;
+                            recorded = 0;
+                            rotate_index = index;
+                            rotated.push_back((moved, moved_timing));
+                            while rotated.len() > self.rotate_keep.unwrap_or(usize::MAX) {
+                                if let Some((old, old_timing)) = rotated.pop_front() {
+                                    let _ = fs::remove_file(old);
+                                    if let Some(old_timing) = old_timing {
+                                        let _ = fs::remove_file(old_timing);
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            warn(&format!("{}, rotation stopped", e));
+                            rotate_size = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        let (status, usage) = match exit_status {
+            Some(exit) => exit,
+            None => wait_child(child),
+        };
+
+        if let Some(dropped) = sampler.as_mut().map(Sampler::take_dropped).filter(|&n| n > 0) {
+            rate_dropped += dropped;
+            let dropped = dropped.to_string();
+            write_info(&mut script, &mut *encoder, &mut timing, started.elapsed(), "DROPPED", &dropped);
+        }
+        if rate_dropped > 0 && !info_sink {
+            warn(&format!("{} bytes of output over the rate limit were not recorded", rate_dropped));
+        }
+        // The usage is in the done line, and in advanced timing files as an information entry.
+        // asciicast recordings go without it, as players would show it as a marker.
+        if let Some(t) = timing.as_mut() {
+            if let Err(e) = t.info(started.elapsed(), "RUSAGE", &usage.fields()) {
+                warn(&format!("can not write timing data: {}", e));
+            }
+        }
+        // Short sessions may not have written anything yet, so this is where a full disk shows.
+        // Like one the write error policy has already dealt with, which is not run into again,
+        // it is warned about and does not take the place of the child's status.
+        if !(write_failed && (!recording || paused || dropping)) {
+            let finished = script.write_all(&encoder.trailer(status, &usage)).and_then(|_| script.flush());
+            match finished {
+                Ok(()) => {
+                    let _ = script.get_ref().file().sync_all();
+                }
+                Err(e) if error.is_some() || write_failed || out_of_space(&e).is_some() => {
+                    warn(&format!("can not finish the output file: {}", e));
+                }
+                Err(e) => error = Some(Error::Io(e)),
+            }
+        }
+
+        if let Some(log) = siem.as_mut() {
+            let mut fields = siem_fields;
+            fields.push(("cn1Label", "exitCode".to_owned()));
+            fields.push(("cn1", status.to_string()));
+            if let Err(e) = log.emit("session-end", "Session ended", 3, &fields) {
+                warn(&format!("can not write SIEM event: {}", e));
+            }
+        }
+
+        drop(terminal);
+        if !self.quiet {
+            // The terminal may be gone after a hangup, which must not turn into a panic.
+            let _ = writeln!(io::stdout().lock(), "Script done, output log file is '{}'.", out_path.display());
+        }
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(status),
+        }
+    }
+
+    /// Starts recording into a new output file at `path`: writes the header to it and creates
+    /// the timing file that goes with it at `timing_path`. A timing file that can't be created
+    /// is warned about and left out, as the recording can go on without it.
+    fn start_output(
+        &self,
+        path: &Path,
+        timing_path: Option<&Path>,
+        encoder: &mut dyn Encoder,
+        ws: &winsize,
+        at: Duration,
+        advanced: bool,
+    ) -> Result<(BufWriter<OutputFile>, Option<TimingWriter>)> {
+        let file = open_output(path, self.append).map_err(|e| open_error(path, e))?;
+        let mut script = output_writer(file, self.buffer_capacity(), self.compress);
+        let info = SessionInfo {
+            command: self.command.as_deref(),
+            columns: ws.ws_col,
+            lines: ws.ws_row,
+        };
+        let _ = script.write_all(&encoder.header(&info, at));
+
+        let timing = timing_path.and_then(|path| match TimingWriter::create(path, self.append, at, advanced) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                warn(&format!("can not open {}: {}", path.display(), e));
+                None
+            }
+        });
+        Ok((script, timing))
+    }
+
+    fn buffer_capacity(&self) -> usize {
+        if self.low_overhead {
+            LOW_OVERHEAD_BUFFER
+        } else {
+            OUTPUT_BUFFER
+        }
+    }
+
+    /// The shell's environment as `KEY=VALUE` strings for execve. `SCRIPT` and
+    /// `SCRIPT_SESSION_ID` tell programs in the session that it is being recorded, and where.
+    fn child_env(&self) -> std::result::Result<Vec<CString>, NulError> {
+        let mut vars: Vec<(OsString, OsString)> = std::env::vars_os()
+            .filter(|(key, _)| !self.env_clear || ENV_KEPT.iter().any(|kept| key == kept))
+            .filter(|(key, _)| !self.env_remove.iter().any(|removed| key == removed.as_str()))
+            .filter(|(key, _)| key != "SCRIPT" && key != "SCRIPT_SESSION_ID")
+            .collect();
+        vars.push(("SCRIPT".into(), self.output.clone().into_os_string()));
+        vars.push(("SCRIPT_SESSION_ID".into(), session_id().into()));
+        for (key, value) in &self.env {
+            vars.retain(|(k, _)| k != key.as_str());
+            vars.push((key.into(), value.into()));
+        }
+
+        vars.into_iter()
+            .map(|(key, value)| {
+                let mut var = key.into_vec();
+                var.push(b'=');
+                var.extend_from_slice(value.as_bytes());
+                CString::new(var)
+            })
+            .collect()
+    }
+}
+
+/// Identifies a session among all recorded on this host: the recorder's pid and the start
+/// time.
+fn session_id() -> String {
+    let start = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}-{}", start.as_secs(), getpid())
+}
+
+/// Variables kept by `env_clear`: what a shell needs to start up in the user's terminal.
+const ENV_KEPT: &[&str] = &["HOME", "LOGNAME", "PATH", "SHELL", "TERM", "USER"];
+
+/// CEF extension fields describing the session, shared by its SIEM events.
+fn session_fields(out_path: &Path, child: Pid, command: Option<&str>) -> Vec<(&'static str, String)> {
+    let mut fields = Vec::new();
+
+    let mut buf = [0u8; 256];
+    if let Ok(host) = gethostname(&mut buf) {
+        fields.push(("dvchost", host.to_string_lossy().into_owned()));
+    }
+    if let Ok(user) = std::env::var("USER") {
+        fields.push(("suser", user));
+    }
+    fields.push(("dvcpid", getpid().to_string()));
+    fields.push(("spid", child.to_string()));
+    fields.push(("fname", out_path.display().to_string()));
+
+    let command = command
+        .map(str::to_owned)
+        .or_else(|| std::env::var("SHELL").ok())
+        .unwrap_or_else(|| "/bin/sh".to_owned());
+    fields.push(("cs1Label", "command".to_owned()));
+    fields.push(("cs1", command));
+
+    fields
+}
+
+/// Writes all of `data`, continuing after short writes and interruptions, and waiting for `fd`
+/// to become writable if it is non-blocking.
+fn write_all(fd: RawFd, mut data: &[u8]) -> nix::Result<()> {
+    while !data.is_empty() {
+        match write(fd, data) {
+            Ok(n) => data = &data[n..],
+            Err(nix::Error::Sys(Errno::EINTR)) => {}
+            Err(nix::Error::Sys(Errno::EAGAIN)) => {
+                let mut fds = FdSet::new();
+                fds.insert(fd);
+                select(Some(fd + 1), None, Some(&mut fds), None, None)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Writes to an extra log, giving up on it after the first error.
+fn log_write(file: &mut Option<File>, data: &[u8], name: &str) {
+    if let Some(log) = file.as_mut() {
+        if let Err(e) = log.write_all(data) {
+            warn(&format!("can not write {}: {}", name, e));
+            *file = None;
+        }
+    }
+}
+
+/// Opens an output file. It is close-on-exec, as files opened before the fork must not leak
+/// into the shell.
+fn open_output(path: &Path, append: bool) -> nix::Result<File> {
+    let mode = if append { OFlag::O_APPEND } else { OFlag::O_TRUNC };
+    let fd = open(
+        path,
+        OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_CLOEXEC | mode,
+        Mode::S_IRUSR
+            | Mode::S_IWUSR
+            | Mode::S_IRGRP
+            | Mode::S_IWGRP
+            | Mode::S_IROTH
+            | Mode::S_IWOTH,
+    )?;
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// The errno of a write error that means the file system is full.
+fn out_of_space(e: &io::Error) -> Option<Errno> {
+    match e.raw_os_error().map(Errno::from_i32) {
+        Some(errno) if errno == Errno::ENOSPC || errno == Errno::EDQUOT => Some(errno),
+        _ => None,
+    }
+}
+
+/// The output file, as written or compressed.
+enum OutputFile {
+    Plain(File),
+    Gzip(GzipWriter<File>),
+}
+
+impl OutputFile {
+    fn file(&self) -> &File {
+        match self {
+            OutputFile::Plain(file) => file,
+            OutputFile::Gzip(gzip) => gzip.get_ref(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputFile::Plain(file) => file.write(buf),
+            OutputFile::Gzip(gzip) => gzip.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Gzip(gzip) => gzip.flush(),
+        }
+    }
+}
+
+fn output_writer(file: File, capacity: usize, compress: Option<Compression>) -> BufWriter<OutputFile> {
+    let output = match compress {
+        Some(Compression::Gzip) => OutputFile::Gzip(GzipWriter::new(file)),
+        None => OutputFile::Plain(file),
+    };
+    BufWriter::with_capacity(capacity, output)
+}
+
+/// Throws away output that is still buffered because it could not be written.
+fn discard_buffered(script: BufWriter<OutputFile>) -> BufWriter<OutputFile> {
+    let capacity = script.capacity();
+    let (file, _) = script.into_parts();
+    BufWriter::with_capacity(capacity, file)
+}
+
+fn open_error(path: &Path, e: nix::Error) -> Error {
+    let e = match e {
+        nix::Error::Sys(errno) => io::Error::from_raw_os_error(errno as i32),
+        e => io::Error::other(e),
+    };
+    Error::Open(path.to_owned(), e)
+}
+
+/// Returns the first `n` after `index` for which neither `<base>.<n>` nor, if given,
+/// `<timing>.<n>` exists yet, so that neither file is overwritten.
+fn next_free_index(base: &Path, timing: Option<&Path>, index: usize) -> usize {
+    let mut index = index;
+    loop {
+        index += 1;
+        if !indexed_path(base, index).exists() && !timing.is_some_and(|t| indexed_path(t, index).exists()) {
+            return index;
+        }
+    }
+}
+
+fn indexed_path(base: &Path, index: usize) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(format!(".{}", index));
+    PathBuf::from(path)
+}
+
+/// How often --title-status refreshes the terminal title.
+const TITLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Shows the elapsed recording time and the output file size in the terminal title.
+fn update_title(elapsed: Duration, size: u64) {
+    let secs = elapsed.as_secs();
+    let title = format!(
+        "\x1b]2;script-rs {:02}:{:02}:{:02} {}\x07",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        human_size(size)
+    );
+    let _ = write(STDOUT_FILENO, title.as_bytes());
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+/// Reaps the child and returns its exit code and resource usage.
+fn wait_child(child: Pid) -> (i32, Usage) {
+    loop {
+        match wait4(child, None) {
+            Ok((status, usage)) => {
+                if let Some(code) = exit_code(status) {
+                    return (code, usage);
+                }
+            }
+            Err(nix::Error::Sys(Errno::EINTR)) => {}
+            Err(_) => return (1, Usage::default()),
+        }
+    }
+}
+
+/// Exit code of a terminated child, using 128 plus the signal number like shells do.
+fn exit_code(status: WaitStatus) -> Option<i32> {
+    match status {
+        WaitStatus::Exited(_, code) => Some(code),
+        WaitStatus::Signaled(_, signal, _) => Some(128 + signal as i32),
+        _ => None,
+    }
+}
+
+/// How long a stopped session's child gets to exit after hangup before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Hangs up the child's process group and waits up to `timeout` for the child to exit, killing
+/// the group after that. Returns the child's exit code and resource usage.
+fn stop_child(child: Pid, timeout: Duration) -> (i32, Usage) {
+    let _ = kill(process_group(child), Signal::SIGHUP);
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        match wait4(child, Some(WaitPidFlag::WNOHANG)) {
+            Ok((status, usage)) => match exit_code(status) {
+                Some(code) => return (code, usage),
+                None => thread::sleep(Duration::from_millis(50)),
+            },
+            Err(_) => return (1, Usage::default()),
+        }
+    }
+
+    let _ = kill(process_group(child), Signal::SIGKILL);
+    wait_child(child)
+}
+
+/// Passes a signal sent to the recorder on to the job in the foreground of the session, as the
+/// terminal would for ^C, and to the child's own process group.
+fn forward_signal(master_fd: RawFd, child: Pid, signal: Signal) {
+    if let Ok(group) = tcgetpgrp(master_fd) {
+        if group != child {
+            let _ = kill(process_group(group), signal);
+        }
+    }
+    let _ = kill(process_group(child), signal);
+}
+
+/// The pid `kill` takes to signal a whole process group. The child leads its own session, so
+/// its pid is also its process group id.
+fn process_group(leader: Pid) -> Pid {
+    Pid::from_raw(-leader.as_raw())
+}
+
+/// Size of the buffer for a single read from stdin or the pty.
+const RELAY_BUFFER: usize = 64 * 1024;
+
+/// Output buffer size, and the larger one used in low overhead mode.
+const OUTPUT_BUFFER: usize = 8 * 1024;
+const LOW_OVERHEAD_BUFFER: usize = 1024 * 1024;
+
+/// Shortest suspend recorded as a gap, so that scheduling noise isn't mistaken for one.
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Writes an information entry to the recording and, in the advanced format, the timing file.
+fn write_info(
+    script: &mut BufWriter<OutputFile>,
+    encoder: &mut dyn Encoder,
+    timing: &mut Option<TimingWriter>,
+    at: Duration,
+    name: &str,
+    value: &str,
+) {
+    let _ = script.write_all(&encoder.info(at, name, value));
+    if let Some(t) = timing.as_mut() {
+        if let Err(e) = t.info(at, name, value) {
+            warn(&format!("can not write timing data: {}", e));
+            *timing = None;
+        }
+    }
+}
+
+/// Passes the end of piped input on to the child as the pty's EOF character, ^D by default.
+/// Like util-linux script, this waits a while for the child to read the input before it, since
+/// an EOF queued behind unread input can get lost when the child changes the terminal mode.
+fn send_eof(master_fd: RawFd, slave_name: &str) {
+    let flags = OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_CLOEXEC;
+    if let Ok(slave) = open(Path::new(slave_name), flags, Mode::empty()) {
+        for _ in 0..8 {
+            let mut fds = FdSet::new();
+            fds.insert(slave);
+            let mut timeout = TimeVal::milliseconds(10);
+            match select(Some(slave + 1), Some(&mut fds), None, None, Some(&mut timeout)) {
+                Ok(n) if n > 0 => thread::sleep(Duration::from_millis(250)),
+                _ => break,
+            }
+        }
+        let _ = close(slave);
+    }
+    let _ = write(master_fd, b"\x04");
+}
+
+/// Installs the handlers for the signals the relay loop reacts to. SIGINT, SIGTERM and SIGHUP
+/// are passed on to the child rather than killing the recorder, so that the recording is
+/// finished whoever ends the session; the terminal doesn't send SIGINT in raw mode, so that
+/// only comes with piped input or from `kill`.
+fn install_handlers() -> Result<SavedHandlers> {
+    let handlers: [(Signal, extern "C" fn(nix::libc::c_int)); 5] = [
+        (Signal::SIGWINCH, on_resize),
+        (Signal::SIGCHLD, on_child_exit),
+        (Signal::SIGHUP, on_hangup),
+        (Signal::SIGTERM, on_terminate),
+        (Signal::SIGINT, on_interrupt),
+    ];
+
+    // Flags left over from an earlier session in the same process must not end this one.
+    for flag in &[&HANGUP, &TERMINATE, &INTERRUPT, &RESIZED, &CHILD_EXITED] {
+        flag.store(false, Ordering::SeqCst);
+    }
+
+    let mut saved = SavedHandlers(Vec::new());
+    for (signal, handler) in handlers {
+        let action = SigAction::new(SigHandler::Handler(handler), SaFlags::empty(), SigSet::empty());
+        saved.0.push((signal, unsafe { sigaction(signal, &action) }?));
+    }
+    Ok(saved)
+}
+
+/// Signal handlers replaced by `install_handlers`, put back on drop so that a program running
+/// a session can still be interrupted or stopped afterwards.
+struct SavedHandlers(Vec<(Signal, SigAction)>);
+
+impl Drop for SavedHandlers {
+    fn drop(&mut self) {
+        for (signal, action) in &self.0 {
+            let _ = unsafe { sigaction(*signal, action) };
+        }
+    }
+}
+
+/// Prints a recorder diagnostic on stderr, which is in raw mode while the session runs.
+fn warn(msg: &str) {
+    let _ = write(STDERR_FILENO, format!("script-rs: {}\r\n", msg).as_bytes());
+}
+
+extern "C" fn on_hangup(_: nix::libc::c_int) {
+    HANGUP.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_resize(_: nix::libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_child_exit(_: nix::libc::c_int) {
+    CHILD_EXITED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_terminate(_: nix::libc::c_int) {
+    TERMINATE.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_interrupt(_: nix::libc::c_int) {
+    INTERRUPT.store(true, Ordering::SeqCst);
+}
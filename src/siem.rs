@@ -0,0 +1,138 @@
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nix::libc;
+
+use crate::clock;
+
+const VENDOR: &str = "script-rs";
+const PRODUCT: &str = "script-rs";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Event format understood by the SIEM.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SiemFormat {
+    /// ArcSight Common Event Format.
+    Cef,
+    /// IBM QRadar Log Event Extended Format 1.0.
+    Leef,
+}
+
+impl FromStr for SiemFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cef" => Ok(SiemFormat::Cef),
+            "leef" => Ok(SiemFormat::Leef),
+            _ => Err(format!("unknown SIEM format: {}", s)),
+        }
+    }
+}
+
+enum Target {
+    File(File),
+    Syslog,
+}
+
+/// Emits session lifecycle events for SIEM ingestion, either appended to a file or sent to
+/// syslog (facility authpriv).
+pub struct SiemLog {
+    format: SiemFormat,
+    target: Target,
+    utc: bool,
+}
+
+impl SiemLog {
+    /// Opens `target`, which is a file path or `syslog`. `utc` selects UTC for LEEF's devTime.
+    pub fn open(target: &Path, format: SiemFormat, utc: bool) -> io::Result<SiemLog> {
+        let target = if target == Path::new("syslog") {
+            unsafe { libc::openlog(b"script-rs\0".as_ptr() as *const _, libc::LOG_PID, libc::LOG_AUTHPRIV) };
+            Target::Syslog
+        } else {
+            Target::File(OpenOptions::new().append(true).create(true).open(target)?)
+        };
+
+        Ok(SiemLog { format, target, utc })
+    }
+
+    /// Emits one event stamped with the current time. `fields` use CEF extension key names;
+    /// they are passed through as is for LEEF, which accepts custom keys.
+    pub fn emit(&mut self, id: &str, name: &str, severity: u8, fields: &[(&str, String)]) -> io::Result<()> {
+        let line = match self.format {
+            SiemFormat::Cef => {
+                let mut line = format!(
+                    "CEF:0|{}|{}|{}|{}|{}|{}|",
+                    escape_header(VENDOR),
+                    escape_header(PRODUCT),
+                    escape_header(VERSION),
+                    escape_header(id),
+                    escape_header(name),
+                    severity
+                );
+                let rt = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|t| t.as_millis())
+                    .unwrap_or(0);
+                let extension: Vec<String> = [("rt", rt.to_string())]
+                    .iter()
+                    .chain(fields)
+                    .map(|(key, value)| format!("{}={}", key, escape_cef_value(value)))
+                    .collect();
+                line.push_str(&extension.join(" "));
+                line
+            }
+            SiemFormat::Leef => {
+                let mut line = format!(
+                    "LEEF:1.0|{}|{}|{}|{}|",
+                    escape_header(VENDOR),
+                    escape_header(PRODUCT),
+                    escape_header(VERSION),
+                    escape_header(id)
+                );
+                let header = [
+                    ("devTime", clock::now(self.utc)),
+                    ("devTimeFormat", "yyyy-MM-dd'T'HH:mm:ssXXX".to_owned()),
+                    ("sev", severity.to_string()),
+                ];
+                let attributes: Vec<String> = header
+                    .iter()
+                    .chain(fields)
+                    .map(|(key, value)| format!("{}={}", key, value.replace(['\t', '\n', '\r'], " ")))
+                    .collect();
+                line.push_str(&attributes.join("\t"));
+                line
+            }
+        };
+
+        match self.target {
+            Target::File(ref mut file) => writeln!(file, "{}", line),
+            Target::Syslog => {
+                let message = CString::new(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                unsafe {
+                    libc::syslog(
+                        libc::LOG_NOTICE,
+                        b"%s\0".as_ptr() as *const _,
+                        message.as_ptr(),
+                    )
+                };
+                Ok(())
+            }
+        }
+    }
+}
+
+fn escape_header(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn escape_cef_value(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
@@ -0,0 +1,43 @@
+use std::ffi::CString;
+
+use crate::clock;
+
+/// Prefixes each line of output with the wall clock time.
+///
+/// A line is stamped when its first byte arrives, so a line printed in pieces carries the
+/// time it was started. Only a line feed starts a new line: carriage returns, as used to
+/// redraw progress bars, stay within the line.
+pub struct LineStamper {
+    format: CString,
+    utc: bool,
+    line_start: bool,
+}
+
+impl LineStamper {
+    /// `format` is a strftime(3) format.
+    pub fn new(format: CString, utc: bool) -> LineStamper {
+        LineStamper {
+            format,
+            utc,
+            line_start: true,
+        }
+    }
+
+    pub fn apply(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 32);
+        // All lines in a chunk arrived at the same time.
+        let mut stamp = None;
+        for &byte in data {
+            if self.line_start {
+                let stamp = stamp.get_or_insert_with(|| clock::format(&self.format, self.utc));
+                out.extend_from_slice(stamp.as_bytes());
+                self.line_start = false;
+            }
+            out.push(byte);
+            if byte == b'\n' {
+                self.line_start = true;
+            }
+        }
+        out
+    }
+}
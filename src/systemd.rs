@@ -0,0 +1,60 @@
+use std::env;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// Minimal sd_notify(3) client for running as a `Type=notify` service.
+pub struct Notifier {
+    socket: UnixDatagram,
+    path: String,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// Connects to `$NOTIFY_SOCKET` if systemd set it, and removes the notify variables from the
+    /// environment so that the child shell doesn't inherit them.
+    pub fn from_env() -> Option<Notifier> {
+        let path = env::var("NOTIFY_SOCKET").ok()?;
+
+        let watchdog = match env::var("WATCHDOG_PID").ok() {
+            Some(pid) if pid != std::process::id().to_string() => None,
+            _ => env::var("WATCHDOG_USEC")
+                .ok()
+                .and_then(|usec| usec.parse().ok())
+                .map(Duration::from_micros),
+        };
+
+        env::remove_var("NOTIFY_SOCKET");
+        env::remove_var("WATCHDOG_PID");
+        env::remove_var("WATCHDOG_USEC");
+
+        Some(Notifier {
+            socket: UnixDatagram::unbound().ok()?,
+            path,
+            watchdog,
+        })
+    }
+
+    /// Sends a state such as `READY=1`, ignoring failures like sd_notify does.
+    pub fn notify(&self, state: &str) {
+        if let Some(name) = self.path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            {
+                use std::os::linux::net::SocketAddrExt;
+                use std::os::unix::net::SocketAddr;
+
+                if let Ok(addr) = SocketAddr::from_abstract_name(name) {
+                    let _ = self.socket.send_to_addr(state.as_bytes(), &addr);
+                }
+            }
+            #[cfg(not(target_os = "linux"))]
+            let _ = name;
+        } else {
+            let _ = self.socket.send_to(state.as_bytes(), &self.path);
+        }
+    }
+
+    /// How often to send `WATCHDOG=1`: half the watchdog timeout, as systemd recommends.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+}
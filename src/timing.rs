@@ -0,0 +1,151 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Writes scriptreplay(1) compatible timing data: one `<delay> <bytes>` line per output chunk,
+/// where the delay is the time in seconds since the previous chunk.
+///
+/// When input or information like wall clock anchors is logged too, the file uses util-linux's
+/// advanced format instead, where each line starts with `O` for output, `I` for input or `H`
+/// for information.
+pub struct TimingWriter {
+    file: File,
+    last: Duration,
+    advanced: bool,
+}
+
+impl TimingWriter {
+    /// Creates the timing file for output starting at `start` into the session. `advanced`
+    /// selects the format that can also record input.
+    pub fn create(path: &Path, append: bool, start: Duration, advanced: bool) -> io::Result<TimingWriter> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        Ok(TimingWriter {
+            file,
+            last: start,
+            advanced,
+        })
+    }
+
+    pub fn record(&mut self, bytes: usize, at: Duration) -> io::Result<()> {
+        self.entry('O', bytes, at)
+    }
+
+    /// Records a chunk of input. Only written in the advanced format.
+    pub fn record_input(&mut self, bytes: usize, at: Duration) -> io::Result<()> {
+        if !self.advanced {
+            return Ok(());
+        }
+        self.entry('I', bytes, at)
+    }
+
+    /// Records a `H <delay> <name> <value>` information entry. Only written in the advanced
+    /// format.
+    pub fn info(&mut self, at: Duration, name: &str, value: &str) -> io::Result<()> {
+        if !self.advanced {
+            return Ok(());
+        }
+        let delay = at.checked_sub(self.last).unwrap_or_default();
+        self.last = at;
+        writeln!(self.file, "H {}.{:06} {} {}", delay.as_secs(), delay.subsec_micros(), name, escape(value))
+    }
+
+    /// Records a change of the terminal size as util-linux does, as a `SIGWINCH` signal entry.
+    /// Only written in the advanced format.
+    pub fn resize(&mut self, at: Duration, columns: u16, lines: u16) -> io::Result<()> {
+        if !self.advanced {
+            return Ok(());
+        }
+        let delay = at.checked_sub(self.last).unwrap_or_default();
+        self.last = at;
+        writeln!(
+            self.file,
+            "S {}.{:06} SIGWINCH ROWS={} COLS={}",
+            delay.as_secs(),
+            delay.subsec_micros(),
+            lines,
+            columns
+        )
+    }
+
+    fn entry(&mut self, stream: char, bytes: usize, at: Duration) -> io::Result<()> {
+        let delay = at.checked_sub(self.last).unwrap_or_default();
+        self.last = at;
+        if self.advanced {
+            write!(self.file, "{} ", stream)?;
+        }
+        writeln!(self.file, "{}.{:06} {}", delay.as_secs(), delay.subsec_micros(), bytes)
+    }
+}
+
+/// Escapes backslashes and control characters in an information value the way Rust string
+/// literals do. Values such as file names come from outside, and a newline in one would
+/// otherwise end the entry and start a forged one.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || c.is_control() {
+            escaped.extend(c.escape_debug());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn written(name: &str, advanced: bool, write: impl FnOnce(&mut TimingWriter)) -> String {
+        let path = std::env::temp_dir().join(format!("script-rs-timing-{}-{}", name, std::process::id()));
+        let mut timing = TimingWriter::create(&path, false, Duration::from_secs(0), advanced).unwrap();
+        write(&mut timing);
+        let data = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        data
+    }
+
+    #[test]
+    fn classic_format() {
+        let data = written("classic", false, |t| {
+            t.record(5, Duration::from_millis(1500)).unwrap();
+            t.record_input(2, Duration::from_secs(2)).unwrap();
+            t.info(Duration::from_secs(2), "MARKER", "1").unwrap();
+            t.record(7, Duration::from_secs(3)).unwrap();
+        });
+        assert_eq!(data, "1.500000 5\n1.500000 7\n");
+    }
+
+    #[test]
+    fn advanced_format() {
+        let data = written("advanced", true, |t| {
+            t.record(5, Duration::from_secs(1)).unwrap();
+            t.record_input(2, Duration::from_secs(2)).unwrap();
+            t.info(Duration::from_secs(2), "MARKER", "1").unwrap();
+            t.resize(Duration::from_secs(3), 100, 40).unwrap();
+        });
+        assert_eq!(
+            data,
+            "O 1.000000 5\nI 1.000000 2\nH 0.000000 MARKER 1\nS 1.000000 SIGWINCH ROWS=40 COLS=100\n"
+        );
+    }
+
+    #[test]
+    fn info_values_stay_on_one_line() {
+        let data = written("escape", true, |t| {
+            t.info(Duration::from_secs(0), "FILE", "created w/evil\nO 0.0 99999").unwrap();
+            t.info(Duration::from_secs(0), "FILE", "created a\\b\r\t\u{1b}[31m caf\u{e9}").unwrap();
+        });
+        assert_eq!(
+            data,
+            "H 0.000000 FILE created w/evil\\nO 0.0 99999\nH 0.000000 FILE created a\\\\b\\r\\t\\u{1b}[31m caf\u{e9}\n"
+        );
+    }
+}
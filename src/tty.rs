@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use nix::libc::{atexit, STDIN_FILENO, STDOUT_FILENO};
+use nix::sys::termios::*;
+use nix::unistd::write;
+
+use crate::error::{Error, Result};
+
+/// Set while the terminal title is saved on the title stack, to pop it on exit.
+pub(crate) static TITLE_STATUS: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// Terminal settings from before raw mode, restored by `reset_tty`.
+    static ref TERMIOS: Mutex<Option<Termios>> = Mutex::new(None);
+}
+
+/// Puts the terminal on stdin in raw mode, and arranges for `reset_tty` to run at exit.
+pub fn enter_raw_mode() -> Result<()> {
+    let termios = tty_set_row(STDIN_FILENO).map_err(Error::terminal)?;
+    *TERMIOS.lock().unwrap() = Some(termios);
+    unsafe { atexit(reset_tty) };
+    Ok(())
+}
+
+/// Switches `fd` to raw mode and returns its previous settings.
+fn tty_set_row(fd: i32) -> nix::Result<Termios> {
+    let prev_termios = tcgetattr(fd)?;
+    let mut termios = prev_termios.clone();
+    cfmakeraw(&mut termios);
+    tcsetattr(fd, SetArg::TCSAFLUSH, &termios)?;
+    Ok(prev_termios)
+}
+
+/// Restores the terminal settings saved by `enter_raw_mode`. They are only restored once, so
+/// that changes made to the terminal afterwards aren't undone at exit.
+pub extern "C" fn reset_tty() {
+    // The terminal may already be gone after a hangup.
+    if let Some(termios) = TERMIOS.lock().unwrap().take() {
+        let _ = tcsetattr(STDIN_FILENO, SetArg::TCSANOW, &termios);
+    }
+
+    if TITLE_STATUS.swap(false, Ordering::SeqCst) {
+        let _ = write(STDOUT_FILENO, b"\x1b[23;2t");
+    }
+}
+
+/// Calls `reset_tty` when dropped, so that the terminal is put back on every way out.
+pub(crate) struct RestoreTerminal;
+
+impl Drop for RestoreTerminal {
+    fn drop(&mut self) {
+        reset_tty();
+    }
+}
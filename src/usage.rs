@@ -0,0 +1,63 @@
+use std::mem;
+use std::time::Duration;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+
+/// Resources used by the child and its reaped descendants, as reported by wait4(2).
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    /// CPU time spent in user mode.
+    pub user: Duration,
+    /// CPU time spent in the kernel.
+    pub system: Duration,
+    /// Peak resident set size in KiB.
+    pub max_rss: u64,
+    /// Blocks read from and written to file systems.
+    pub blocks_in: u64,
+    pub blocks_out: u64,
+}
+
+impl Usage {
+    /// Formats the usage as `KEY="value"` fields, like the ones on the done line.
+    pub fn fields(&self) -> String {
+        format!(
+            "USER_TIME=\"{}.{:06}\" SYSTEM_TIME=\"{}.{:06}\" MAX_RSS_KB=\"{}\" BLOCKS_IN=\"{}\" BLOCKS_OUT=\"{}\"",
+            self.user.as_secs(),
+            self.user.subsec_micros(),
+            self.system.as_secs(),
+            self.system.subsec_micros(),
+            self.max_rss,
+            self.blocks_in,
+            self.blocks_out
+        )
+    }
+}
+
+/// waitpid(2) that also returns the resource usage of the child once it has terminated.
+pub(crate) fn wait4(child: Pid, options: Option<WaitPidFlag>) -> nix::Result<(WaitStatus, Usage)> {
+    let mut status = 0;
+    let mut rusage: libc::rusage = unsafe { mem::zeroed() };
+    let options = options.map_or(0, |o| o.bits());
+
+    let res = unsafe { libc::wait4(child.into(), &mut status, options, &mut rusage) };
+    match Errno::result(res)? {
+        0 => Ok((WaitStatus::StillAlive, Usage::default())),
+        pid => {
+            let usage = Usage {
+                user: timeval(rusage.ru_utime),
+                system: timeval(rusage.ru_stime),
+                max_rss: rusage.ru_maxrss as u64,
+                blocks_in: rusage.ru_inblock as u64,
+                blocks_out: rusage.ru_oublock as u64,
+            };
+            Ok((WaitStatus::from_raw(Pid::from_raw(pid), status)?, usage))
+        }
+    }
+}
+
+fn timeval(tv: libc::timeval) -> Duration {
+    Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}